@@ -0,0 +1,179 @@
+//! Zero-copy counterparts of the types in the crate root.
+//!
+//! [`MetadataRef`] borrows its strings from the JSON it was parsed from instead of
+//! allocating a `String` for each of them, which saves a lot of allocations when
+//! reading the output of `cargo metadata` for a large dependency graph. Strings that
+//! contain JSON escape sequences (e.g. Windows paths) can't be borrowed and are
+//! allocated as usual.
+//!
+//! Only the most commonly used fields are available. Use [`Metadata`](crate::Metadata)
+//! if you need the others.
+//!
+//! ```
+//! # let json = r#"{"packages":[],"workspace_members":[],"resolve":null,"workspace_root":"/foo","target_directory":"/foo/target","version":1}"#;
+//! let metadata: cargo_metadata::borrowed::MetadataRef = serde_json::from_str(json).unwrap();
+//! assert_eq!(metadata.workspace_root, "/foo");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use crate::DependencyKind;
+
+/// A string that is borrowed from the JSON input if possible.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Str<'a>(#[serde(borrow)] pub Cow<'a, str>);
+
+impl Str<'_> {
+    /// Whether the string is borrowed from the JSON input.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.0, Cow::Borrowed(_))
+    }
+}
+
+impl Deref for Str<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Str<'_> {
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<&str> for Str<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl fmt::Display for Str<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Borrowed counterpart of [`Metadata`](crate::Metadata).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetadataRef<'a> {
+    /// A list of all crates referenced by this crate (and the crate itself)
+    #[serde(borrow)]
+    pub packages: Vec<PackageRef<'a>>,
+    /// A list of all workspace members
+    #[serde(borrow)]
+    pub workspace_members: Vec<Str<'a>>,
+    /// Dependencies graph
+    #[serde(borrow)]
+    pub resolve: Option<ResolveRef<'a>>,
+    /// Workspace root
+    #[serde(borrow)]
+    pub workspace_root: Str<'a>,
+    /// Build directory
+    #[serde(borrow)]
+    pub target_directory: Str<'a>,
+}
+
+/// Borrowed counterpart of [`Package`](crate::Package).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackageRef<'a> {
+    /// The name field as given in the `Cargo.toml`
+    #[serde(borrow)]
+    pub name: Str<'a>,
+    /// The version field as specified in the `Cargo.toml`
+    #[serde(borrow)]
+    pub version: Str<'a>,
+    /// An opaque identifier for a package
+    #[serde(borrow)]
+    pub id: Str<'a>,
+    /// The source of the package, e.g. crates.io or `None` for local projects.
+    #[serde(borrow)]
+    pub source: Option<Str<'a>>,
+    /// List of dependencies of this particular package
+    #[serde(borrow)]
+    pub dependencies: Vec<DependencyRef<'a>>,
+    /// Targets provided by the crate (lib, bin, example, test, ...)
+    #[serde(borrow)]
+    pub targets: Vec<TargetRef<'a>>,
+    /// Features provided by the crate, mapped to the features required by that feature.
+    #[serde(borrow)]
+    pub features: BTreeMap<Str<'a>, Vec<Str<'a>>>,
+    /// Path containing the `Cargo.toml`
+    #[serde(borrow)]
+    pub manifest_path: Str<'a>,
+}
+
+/// Borrowed counterpart of [`Dependency`](crate::Dependency).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DependencyRef<'a> {
+    /// Name as given in the `Cargo.toml`
+    #[serde(borrow)]
+    pub name: Str<'a>,
+    /// The source of dependency
+    #[serde(borrow)]
+    pub source: Option<Str<'a>>,
+    /// The required version
+    #[serde(borrow)]
+    pub req: Str<'a>,
+    /// The kind of dependency this is
+    #[serde(deserialize_with = "crate::dependency::parse_dependency_kind")]
+    pub kind: DependencyKind,
+    /// Whether this dependency is required or optional
+    pub optional: bool,
+    /// If the dependency is renamed, this is the new name for the dependency
+    #[serde(borrow, default)]
+    pub rename: Option<Str<'a>>,
+}
+
+/// Borrowed counterpart of [`Target`](crate::Target).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TargetRef<'a> {
+    /// Name as given in the `Cargo.toml` or generated from the file name
+    #[serde(borrow)]
+    pub name: Str<'a>,
+    /// Kind of target
+    #[serde(borrow)]
+    pub kind: Vec<Str<'a>>,
+    /// Path to the main source file of the target
+    #[serde(borrow)]
+    pub src_path: Str<'a>,
+}
+
+/// Borrowed counterpart of [`Resolve`](crate::Resolve).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolveRef<'a> {
+    /// Nodes in a dependencies graph
+    #[serde(borrow)]
+    pub nodes: Vec<NodeRef<'a>>,
+    /// The crate for which the metadata was read.
+    #[serde(borrow)]
+    pub root: Option<Str<'a>>,
+}
+
+/// Borrowed counterpart of [`Node`](crate::Node).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NodeRef<'a> {
+    /// An opaque identifier for a package
+    #[serde(borrow)]
+    pub id: Str<'a>,
+    /// List of opaque identifiers for this node's dependencies.
+    #[serde(borrow)]
+    pub dependencies: Vec<Str<'a>>,
+    /// Features enabled on the crate
+    #[serde(borrow, default)]
+    pub features: Vec<Str<'a>>,
+}
@@ -8,19 +8,23 @@ use derive_builder::Builder;
 use semver::VersionReq;
 use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Eq, PartialEq, Clone, Debug, Copy, Hash, Serialize, Deserialize, Default)]
+#[derive(
+    Eq, PartialEq, Clone, Debug, Copy, Hash, Serialize, Deserialize, Default, PartialOrd, Ord,
+)]
 /// Dependencies can come in three kinds
+///
+/// They are ordered as normal, build and then dev dependencies.
 pub enum DependencyKind {
     #[serde(rename = "normal")]
     #[default]
     /// The 'normal' kind
     Normal,
-    #[serde(rename = "dev")]
-    /// Those used in tests only
-    Development,
     #[serde(rename = "build")]
     /// Those used in build scripts only
     Build,
+    #[serde(rename = "dev")]
+    /// Those used in tests only
+    Development,
     #[doc(hidden)]
     #[serde(other)]
     Unknown,
@@ -82,4 +86,85 @@ pub struct Dependency {
     pub path: Option<Utf8PathBuf>,
 }
 
-pub use cargo_platform::Platform;
+impl Dependency {
+    /// Whether this dependency is used when building for the target `triple`, given the
+    /// `cfg` values of that target (see `rustc --print cfg --target <triple>`).
+    ///
+    /// Dependencies without a [`target`](Dependency::target) apply to every target.
+    pub fn applies_to(&self, cfg: &[Cfg], triple: &str) -> bool {
+        self.target
+            .as_ref()
+            .map_or(true, |platform| platform.matches(triple, cfg))
+    }
+
+    /// Classifies where this dependency comes from.
+    ///
+    /// Returns `None` if the source can't be determined. This is the case for path
+    /// dependencies with cargo versions older than 1.51, which don't report
+    /// [`path`](Dependency::path).
+    pub fn kind_of_source(&self) -> Option<DependencySource> {
+        if let Some(path) = &self.path {
+            return Some(DependencySource::Path(path.clone()));
+        }
+        let source = self.source.as_deref()?;
+        if let Some(git) = source.strip_prefix("git+") {
+            let url = git.split('#').next().unwrap_or(git);
+            let (url, query) = url.split_once('?').unwrap_or((url, ""));
+            let reference = query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find_map(|(key, value)| match key {
+                    "branch" => Some(GitReference::Branch(value.to_owned())),
+                    "tag" => Some(GitReference::Tag(value.to_owned())),
+                    "rev" => Some(GitReference::Rev(value.to_owned())),
+                    _ => None,
+                });
+            return Some(DependencySource::Git {
+                url: url.to_owned(),
+                reference,
+            });
+        }
+        if crate::is_crates_io_source(source) {
+            return Some(DependencySource::CratesIo);
+        }
+        crate::registry_source_url(source).map(|index| {
+            DependencySource::Registry(self.registry.clone().unwrap_or_else(|| index.to_owned()))
+        })
+    }
+}
+
+/// Where a [`Dependency`] comes from.
+///
+/// See [`Dependency::kind_of_source`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DependencySource {
+    /// The crates.io registry
+    CratesIo,
+    /// An alternative registry, identified by the URL of its index, without the
+    /// `registry+` or `sparse+` prefix
+    Registry(String),
+    /// A git repository
+    Git {
+        /// The URL of the repository
+        url: String,
+        /// The branch, tag or revision to check out, if one is specified
+        reference: Option<GitReference>,
+    },
+    /// A local path
+    Path(Utf8PathBuf),
+}
+
+/// The reference to check out of a git dependency.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GitReference {
+    /// A branch
+    Branch(String),
+    /// A tag
+    Tag(String),
+    /// A revision
+    Rev(String),
+}
+
+pub use cargo_platform::{Cfg, Platform};
@@ -3,7 +3,9 @@
 #[cfg(feature = "builder")]
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::ops::Range;
 
 /// The error code associated to this diagnostic.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -91,8 +93,25 @@ pub struct DiagnosticSpan {
     pub expansion: Option<Box<DiagnosticSpanMacroExpansion>>,
 }
 
+impl DiagnosticSpan {
+    /// The 0-based byte offsets of this span in the file, end exclusive, for slicing the
+    /// file's contents.
+    pub fn byte_range(&self) -> Range<usize> {
+        self.byte_start as usize..self.byte_end as usize
+    }
+
+    /// The 1-based `(line, column)` of the start and the end of this span. Columns count
+    /// characters, not bytes, and the end column is exclusive.
+    pub fn line_col_range(&self) -> ((usize, usize), (usize, usize)) {
+        (
+            (self.line_start, self.column_start),
+            (self.line_end, self.column_end),
+        )
+    }
+}
+
 /// Whether a suggestion can be safely applied.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 #[non_exhaustive]
 pub enum Applicability {
     /// The suggested replacement can be applied automatically safely
@@ -104,9 +123,25 @@ pub enum Applicability {
     /// human review.
     MaybeIncorrect,
     /// The suggested replacement will probably not work.
+    #[default]
     Unspecified,
 }
 
+impl Applicability {
+    /// Whether this is `MachineApplicable`.
+    pub fn is_machine_applicable(&self) -> bool {
+        matches!(self, Applicability::MachineApplicable)
+    }
+
+    /// Whether a suggestion with this applicability may be applied without
+    /// human review.
+    ///
+    /// This is only the case for `MachineApplicable` suggestions.
+    pub fn can_autofix(&self) -> bool {
+        self.is_machine_applicable()
+    }
+}
+
 /// The diagnostic level
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[non_exhaustive]
@@ -126,6 +161,44 @@ pub enum DiagnosticLevel {
     Note,
     /// Help
     Help,
+    #[doc(hidden)]
+    #[serde(other)]
+    Unknown,
+}
+
+impl DiagnosticLevel {
+    /// Whether this level is at least as severe as `min`.
+    ///
+    /// From most to least severe, the levels are internal compiler errors, errors,
+    /// warnings, and finally notes, help messages and failure notes, which are all
+    /// considered equally severe.
+    pub fn is_at_least(self, min: DiagnosticLevel) -> bool {
+        self.severity() >= min.severity()
+    }
+
+    fn severity(self) -> u8 {
+        match self {
+            DiagnosticLevel::Ice => 4,
+            DiagnosticLevel::Error => 3,
+            DiagnosticLevel::Warning => 2,
+            DiagnosticLevel::FailureNote | DiagnosticLevel::Note | DiagnosticLevel::Help => 1,
+            DiagnosticLevel::Unknown => 0,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DiagnosticLevel::Ice => "error: internal compiler error",
+            DiagnosticLevel::Error => "error",
+            DiagnosticLevel::Warning => "warning",
+            DiagnosticLevel::FailureNote => "failure-note",
+            DiagnosticLevel::Note => "note",
+            DiagnosticLevel::Help => "help",
+            DiagnosticLevel::Unknown => "unknown",
+        })
+    }
 }
 
 /// A diagnostic message generated by rustc
@@ -148,6 +221,22 @@ pub struct Diagnostic {
     pub rendered: Option<String>,
 }
 
+/// A machine-applicable replacement of a byte range in a source file.
+///
+/// See [`Diagnostic::machine_applicable_suggestions`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Suggestion {
+    /// The file the replacement applies to.
+    pub file_name: String,
+    /// The byte offset in the file where the replaced range starts.
+    pub byte_start: u32,
+    /// The byte offset in the file where the replaced range ends.
+    pub byte_end: u32,
+    /// The text to put in place of the range.
+    pub replacement: String,
+}
+
 impl fmt::Display for Diagnostic {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(ref rendered) = self.rendered {
@@ -158,3 +247,168 @@ impl fmt::Display for Diagnostic {
         Ok(())
     }
 }
+
+impl Diagnostic {
+    /// Whether this diagnostic is an error, including internal compiler errors.
+    pub fn is_error(&self) -> bool {
+        matches!(self.level, DiagnosticLevel::Error | DiagnosticLevel::Ice)
+    }
+
+    /// Renders this diagnostic from its structured spans, in the style of rustc
+    /// but without any color codes.
+    ///
+    /// Unlike `rendered`, this is always available. Children are rendered
+    /// after their parent, indented by one level.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.render_into(&mut out, 0)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Collects the suggested replacements of this diagnostic and all its
+    /// children that are marked as `MachineApplicable`.
+    pub fn machine_applicable_suggestions(&self) -> Vec<Suggestion> {
+        let mut suggestions = Vec::new();
+        self.collect_suggestions(&mut suggestions);
+        suggestions
+    }
+
+    fn collect_suggestions(&self, suggestions: &mut Vec<Suggestion>) {
+        for span in &self.spans {
+            match (&span.suggested_replacement, span.suggestion_applicability) {
+                (Some(replacement), Some(applicability)) if applicability.can_autofix() => {
+                    suggestions.push(Suggestion {
+                        file_name: span.file_name.clone(),
+                        byte_start: span.byte_start,
+                        byte_end: span.byte_end,
+                        replacement: replacement.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        for child in &self.children {
+            child.collect_suggestions(suggestions);
+        }
+    }
+
+    /// Iterates over the spans of this diagnostic and, recursively, of all its
+    /// children, parents before children.
+    pub fn all_spans(&self) -> impl Iterator<Item = &DiagnosticSpan> {
+        let mut spans = Vec::new();
+        self.collect_spans(&mut spans);
+        spans.into_iter()
+    }
+
+    fn collect_spans<'a>(&'a self, spans: &mut Vec<&'a DiagnosticSpan>) {
+        spans.extend(&self.spans);
+        for child in &self.children {
+            child.collect_spans(spans);
+        }
+    }
+
+    /// The files touched by this diagnostic and its children, as given in
+    /// [`DiagnosticSpan::file_name`].
+    ///
+    /// Synthetic names in angle brackets, such as `<anon>` or the name of a macro that
+    /// rustc reports instead of a file, are skipped.
+    pub fn affected_files(&self) -> BTreeSet<String> {
+        self.all_spans()
+            .map(|span| &span.file_name)
+            .filter(|name| !(name.starts_with('<') && name.ends_with('>')))
+            .cloned()
+            .collect()
+    }
+
+    /// The first of this diagnostic's own spans that is marked as primary, i.e.
+    /// where the problem occurred. The spans of the children are not considered.
+    pub fn primary_span(&self) -> Option<&DiagnosticSpan> {
+        self.spans.iter().find(|span| span.is_primary)
+    }
+
+    /// Returns `rendered` with ANSI color sequences (`ESC [ ... m`) removed.
+    ///
+    /// Other escape sequences are left untouched.
+    pub fn rendered_plain(&self) -> Option<String> {
+        self.rendered.as_deref().map(strip_sgr)
+    }
+
+    fn render_into(&self, out: &mut String, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        write!(out, "{}{}", indent, self.level)?;
+        if let Some(code) = &self.code {
+            write!(out, "[{}]", code.code)?;
+        }
+        writeln!(out, ": {}", self.message)?;
+
+        for span in &self.spans {
+            let last_line = span.line_start + span.text.len().saturating_sub(1);
+            let width = last_line.to_string().len();
+            let gutter = " ".repeat(width);
+            writeln!(
+                out,
+                "{}{}--> {}:{}:{}",
+                indent, gutter, span.file_name, span.line_start, span.column_start
+            )?;
+            if span.text.is_empty() {
+                continue;
+            }
+            writeln!(out, "{}{} |", indent, gutter)?;
+            let marker = if span.is_primary { "^" } else { "-" };
+            for (i, line) in span.text.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{}{:>width$} | {}",
+                    indent,
+                    span.line_start + i,
+                    line.text,
+                    width = width
+                )?;
+                let start = line.highlight_start.saturating_sub(1);
+                let len = line
+                    .highlight_end
+                    .saturating_sub(line.highlight_start)
+                    .max(1);
+                write!(
+                    out,
+                    "{}{} | {}{}",
+                    indent,
+                    gutter,
+                    " ".repeat(start),
+                    marker.repeat(len)
+                )?;
+                match &span.label {
+                    Some(label) if i + 1 == span.text.len() => writeln!(out, " {}", label)?,
+                    _ => writeln!(out)?,
+                }
+            }
+        }
+
+        for child in &self.children {
+            child.render_into(out, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes ANSI SGR sequences (`ESC [ <params> m`) from `s`.
+fn strip_sgr(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(esc) = rest.find("\x1b[") {
+        out.push_str(&rest[..esc]);
+        let after = &rest[esc + 2..];
+        let params = after
+            .find(|c: char| !(c.is_ascii_digit() || c == ';'))
+            .unwrap_or(after.len());
+        if after[params..].starts_with('m') {
+            rest = &after[params + 1..];
+        } else {
+            out.push_str("\x1b[");
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -1,4 +1,6 @@
-use std::{io, str::Utf8Error, string::FromUtf8Error};
+use std::{io, str::Utf8Error, string::FromUtf8Error, time::Duration};
+
+use crate::PackageId;
 
 /// Custom result type for `cargo_metadata::Error`
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -30,6 +32,13 @@ pub enum Error {
         stderr: String,
     },
 
+    /// Error during execution of `cargo build`, see [`BuildCommand::exec`](crate::BuildCommand::exec)
+    #[error("`cargo build` exited with an error: {stderr}")]
+    CargoBuild {
+        /// stderr returned by the `cargo build` command
+        stderr: String,
+    },
+
     /// IO Error during execution of `cargo metadata`
     #[error("failed to start `cargo metadata`: {0}")]
     Io(#[from] io::Error),
@@ -49,4 +58,101 @@ pub enum Error {
     /// The output did not contain any json
     #[error("could not find any json in the output of `cargo metadata`")]
     NoJson,
+
+    /// `cargo metadata` didn't finish within the time set with
+    /// [`MetadataCommand::timeout`](crate::MetadataCommand::timeout) and was killed
+    #[error("`cargo metadata` timed out after {elapsed:?}")]
+    Timeout {
+        /// How long `cargo metadata` ran before it was killed
+        elapsed: Duration,
+    },
+
+    /// The package selected with [`MetadataCommand::package`](crate::MetadataCommand::package)
+    /// is not part of the metadata
+    #[error("package `{name}` not found in the output of `cargo metadata`")]
+    PackageNotFound {
+        /// The name of the package
+        name: String,
+    },
+
+    /// The json contained fields that this crate doesn't know about, see
+    /// [`MetadataCommand::strict`](crate::MetadataCommand::strict)
+    #[error("unknown fields in `cargo metadata`'s json: {}", paths.join(", "))]
+    UnknownFields {
+        /// The paths of the unknown fields, e.g. `packages.0.foo`
+        paths: Vec<String>,
+    },
+}
+
+/// Error returned by [`Package::default_binary`](crate::Package::default_binary) when
+/// `cargo run` would not be able to pick a binary on its own.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum RunSelectionError {
+    /// The package doesn't have any binary targets
+    #[error("the package has no bin target to run")]
+    NoBinaries,
+
+    /// The binary named by `default-run` doesn't exist
+    #[error("default-run target `{name}` not found")]
+    DefaultRunNotFound {
+        /// The `default-run` value given in the `Cargo.toml`
+        name: String,
+    },
+
+    /// The package has multiple binaries and no `default-run`
+    #[error("could not determine which binary to run, available binaries: {}", candidates.join(", "))]
+    Ambiguous {
+        /// The names of all binary targets of the package
+        candidates: Vec<String>,
+    },
+}
+
+/// An inconsistency found by [`Metadata::validate`](crate::Metadata::validate).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// A workspace member is not in the list of packages
+    #[error("workspace member `{id}` is not a known package")]
+    UnknownWorkspaceMember {
+        /// The id of the workspace member
+        id: PackageId,
+    },
+
+    /// The root of the dependency graph is not in the list of packages
+    #[error("resolve root `{id}` is not a known package")]
+    UnknownRoot {
+        /// The id of the root
+        id: PackageId,
+    },
+
+    /// A node of the dependency graph is not in the list of packages
+    #[error("resolve node `{id}` is not a known package")]
+    UnknownNode {
+        /// The id of the node
+        id: PackageId,
+    },
+
+    /// A dependency of a node of the dependency graph is not in the list of packages
+    #[error("`{from}` depends on `{to}`, which is not a known package")]
+    UnknownDependency {
+        /// The id of the depending node
+        from: PackageId,
+        /// The id of the unknown dependency
+        to: PackageId,
+    },
+}
+
+/// Error returned when parsing a [`FeatureValue`](crate::FeatureValue) fails.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum FeatureValueError {
+    /// The value doesn't follow cargo's feature value syntax
+    #[error("invalid feature value `{value}`: {reason}")]
+    Invalid {
+        /// The value that failed to parse
+        value: String,
+        /// What is wrong with the value
+        reason: &'static str,
+    },
 }
@@ -0,0 +1,94 @@
+//! This module contains `FeatureValue`, the parsed form of the values in `Package::features`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::FeatureValueError;
+
+/// One of the values a feature enables, as listed in [`Package::features`].
+///
+/// [`Package::features`]: crate::Package::features
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum FeatureValue {
+    /// Another feature of the same package, e.g. `std`.
+    Feature(String),
+    /// An optional dependency, e.g. `dep:serde`.
+    Dep {
+        /// The name of the dependency, which is its rename if it has one
+        dep_name: String,
+    },
+    /// A feature of a dependency, e.g. `serde/derive` or `serde?/derive`.
+    DepFeature {
+        /// The name of the dependency, which is its rename if it has one
+        dep_name: String,
+        /// The feature to enable on the dependency
+        dep_feature: String,
+        /// Whether this is a weak dependency feature (`serde?/derive`), which doesn't
+        /// enable the dependency itself if it is optional.
+        weak: bool,
+    },
+}
+
+impl FromStr for FeatureValue {
+    type Err = FeatureValueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| FeatureValueError::Invalid {
+            value: value.to_owned(),
+            reason,
+        };
+        if value.is_empty() {
+            return Err(invalid("feature values cannot be empty"));
+        }
+        if let Some((dep_name, dep_feature)) = value.split_once('/') {
+            let (dep_name, weak) = match dep_name.strip_suffix('?') {
+                Some(dep_name) => (dep_name, true),
+                None => (dep_name, false),
+            };
+            if dep_name.starts_with("dep:") {
+                return Err(invalid("`dep:` cannot be combined with `/`"));
+            }
+            if dep_name.is_empty() || dep_name.contains('?') {
+                return Err(invalid("expected a dependency name before `/`"));
+            }
+            if dep_feature.is_empty() || dep_feature.contains('/') {
+                return Err(invalid("expected a single feature name after `/`"));
+            }
+            return Ok(FeatureValue::DepFeature {
+                dep_name: dep_name.to_owned(),
+                dep_feature: dep_feature.to_owned(),
+                weak,
+            });
+        }
+        if let Some(dep_name) = value.strip_prefix("dep:") {
+            if dep_name.is_empty() {
+                return Err(invalid("expected a dependency name after `dep:`"));
+            }
+            return Ok(FeatureValue::Dep {
+                dep_name: dep_name.to_owned(),
+            });
+        }
+        if value.contains('?') {
+            return Err(invalid("`?` is only allowed before `/`"));
+        }
+        Ok(FeatureValue::Feature(value.to_owned()))
+    }
+}
+
+impl fmt::Display for FeatureValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureValue::Feature(name) => f.write_str(name),
+            FeatureValue::Dep { dep_name } => write!(f, "dep:{}", dep_name),
+            FeatureValue::DepFeature {
+                dep_name,
+                dep_feature,
+                weak,
+            } => {
+                let weak = if *weak { "?" } else { "" };
+                write!(f, "{}{}/{}", dep_name, weak, dep_feature)
+            }
+        }
+    }
+}
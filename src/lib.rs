@@ -78,34 +78,41 @@
 //! let output = command.wait().expect("Couldn't get cargo's exit status");
 //! ```
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 #[cfg(feature = "builder")]
 use derive_builder::Builder;
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::ffi::OsString;
 use std::fmt;
 use std::hash::Hash;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
 use std::str::{from_utf8, FromStr};
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub use camino;
+pub use cargo_platform;
 pub use semver;
-use semver::Version;
+use semver::{Version, VersionReq};
 
 #[cfg(feature = "builder")]
 pub use dependency::DependencyBuilder;
-pub use dependency::{Dependency, DependencyKind};
+pub use dependency::{Dependency, DependencyKind, DependencySource, GitReference};
 use diagnostic::Diagnostic;
-pub use errors::{Error, Result};
+pub use errors::{Error, FeatureValueError, Result, RunSelectionError, ValidationError};
+pub use features::FeatureValue;
 #[cfg(feature = "unstable")]
-pub use libtest::TestMessage;
+pub use libtest::{TestEvent, TestMessage};
 #[allow(deprecated)]
 pub use messages::parse_messages;
 pub use messages::{
-    Artifact, ArtifactDebuginfo, ArtifactProfile, BuildFinished, BuildScript, CompilerMessage,
-    Message, MessageIter,
+    collect_diagnostics, collect_executables, group_by_package, test_executables, Artifact,
+    ArtifactDebuginfo, ArtifactProfile, BuildFinished, BuildScript, CompilerMessage, Message,
+    MessageIter, MessageResults, OptLevel,
 };
 #[cfg(feature = "builder")]
 pub use messages::{
@@ -114,9 +121,12 @@ pub use messages::{
 };
 use serde::{Deserialize, Deserializer, Serialize};
 
+#[cfg(feature = "borrowed")]
+pub mod borrowed;
 mod dependency;
 pub mod diagnostic;
 mod errors;
+mod features;
 #[cfg(feature = "unstable")]
 pub mod libtest;
 mod messages;
@@ -134,12 +144,129 @@ pub struct PackageId {
     pub repr: String,
 }
 
+impl PackageId {
+    /// The name of the package, parsed from `repr`.
+    ///
+    /// This understands the `name version (source)` format of older cargo versions
+    /// and the [package id specifications](https://doc.rust-lang.org/cargo/reference/pkgid-spec.html)
+    /// used since cargo 1.77. It returns `None` for anything else.
+    pub fn name(&self) -> Option<&str> {
+        self.parts().map(|(name, _, _)| name)
+    }
+
+    /// The version of the package, parsed from `repr`.
+    ///
+    /// See [`PackageId::name`] for the supported formats.
+    pub fn version(&self) -> Option<Version> {
+        self.parts()
+            .and_then(|(_, version, _)| Version::parse(version).ok())
+    }
+
+    /// The URL of the source of the package, like `path+file:///foo` or
+    /// `registry+https://github.com/rust-lang/crates.io-index`, parsed from `repr`.
+    ///
+    /// See [`PackageId::name`] for the supported formats.
+    pub fn source_url(&self) -> Option<&str> {
+        self.parts().map(|(_, _, source)| source)
+    }
+
+    /// The name and version of the package, parsed from `repr`.
+    ///
+    /// See [`PackageId::name`] for the supported formats.
+    pub fn name_version(&self) -> Option<(String, Version)> {
+        let (name, version, _) = self.parts()?;
+        Some((name.to_owned(), Version::parse(version).ok()?))
+    }
+
+    /// A compact representation for user-facing output, like `serde v1.0.136`.
+    ///
+    /// Falls back to `repr` if the name and version can't be parsed from it.
+    pub fn short(&self) -> String {
+        match self.name_version() {
+            Some((name, version)) => format!("{} v{}", name, version),
+            None => self.repr.clone(),
+        }
+    }
+
+    /// Parses `repr` into its kind of source, source URL, name and version.
+    ///
+    /// See [`PackageId::name`] for the supported formats.
+    pub fn parse_spec(&self) -> Option<PackageIdSpec> {
+        let (name, version, source) = self.parts()?;
+        let (kind, url) = source.split_once('+')?;
+        let kind = match kind {
+            "path" => SourceKind::Path,
+            "git" => SourceKind::Git,
+            "registry" => SourceKind::Registry,
+            "sparse" => SourceKind::SparseRegistry,
+            other => SourceKind::Other(other.to_owned()),
+        };
+        Some(PackageIdSpec {
+            kind,
+            url: url.to_owned(),
+            name: name.to_owned(),
+            version: Version::parse(version).ok()?,
+        })
+    }
+
+    /// Splits `repr` into name, version and source.
+    fn parts(&self) -> Option<(&str, &str, &str)> {
+        if let Some(rest) = self.repr.strip_suffix(')') {
+            // `name version (source)`
+            let (name, rest) = rest.split_once(' ')?;
+            let (version, source) = rest.split_once(" (")?;
+            return Some((name, version, source));
+        }
+        // `source#name@version` or `source#version`
+        let (source, fragment) = self.repr.rsplit_once('#')?;
+        match fragment.split_once('@') {
+            Some((name, version)) => Some((name, version, source)),
+            None => {
+                let path = source.split('?').next()?.trim_end_matches('/');
+                let name = path.rsplit('/').next()?;
+                Some((name, fragment, source))
+            }
+        }
+    }
+}
+
 impl fmt::Display for PackageId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.repr, f)
     }
 }
 
+/// The parts of a [`PackageId`], as returned by [`PackageId::parse_spec`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct PackageIdSpec {
+    /// The kind of source the package comes from
+    pub kind: SourceKind,
+    /// The URL of the source without the kind prefix, e.g. `file:///foo` or
+    /// `https://github.com/rust-lang/crates.io-index`
+    pub url: String,
+    /// The name of the package
+    pub name: String,
+    /// The version of the package
+    pub version: Version,
+}
+
+/// The kind of source of a package, i.e. the prefix of its source URL.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SourceKind {
+    /// A local path (`path+`)
+    Path,
+    /// A git repository (`git+`)
+    Git,
+    /// A registry using the git index protocol (`registry+`)
+    Registry,
+    /// A registry using the sparse index protocol (`sparse+`)
+    SparseRegistry,
+    /// Any other kind of source, e.g. `directory` for vendored sources
+    Other(String),
+}
+
 /// Helpers for default metadata fields
 fn is_null(value: &serde_json::Value) -> bool {
     matches!(value, serde_json::Value::Null)
@@ -177,6 +304,36 @@ pub struct Metadata {
 }
 
 impl Metadata {
+    /// Parses `cargo metadata` output from a reader, e.g. a file or a socket.
+    ///
+    /// See [`MetadataCommand::parse`] to parse it from a string.
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Metadata> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Parses `cargo metadata` output from bytes, without converting them to a `String` first.
+    pub fn from_slice(bytes: &[u8]) -> Result<Metadata> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    /// The format version of the `cargo metadata` output this was parsed from.
+    pub fn format_version(&self) -> usize {
+        self.version
+    }
+
+    /// Deserializes the [`workspace_metadata`](Metadata::workspace_metadata) into `T`.
+    ///
+    /// Returns `Ok(None)` if the workspace has no `workspace.metadata` section.
+    /// See [`Package::metadata_typed`] for the per-package counterpart.
+    pub fn workspace_metadata_typed<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> std::result::Result<Option<T>, serde_json::Error> {
+        if self.workspace_metadata.is_null() {
+            return Ok(None);
+        }
+        T::deserialize(&self.workspace_metadata).map(Some)
+    }
+
     /// Get the workspace's root package of this metadata instance.
     pub fn root_package(&self) -> Option<&Package> {
         match &self.resolve {
@@ -195,14 +352,35 @@ impl Metadata {
         }
     }
 
+    /// Whether the package with the given id is a member of the workspace.
+    pub fn is_workspace_member(&self, id: &PackageId) -> bool {
+        self.workspace_members.contains(id)
+    }
+
     /// Get the workspace packages.
     pub fn workspace_packages(&self) -> Vec<&Package> {
         self.packages
             .iter()
-            .filter(|&p| self.workspace_members.contains(&p.id))
+            .filter(|&p| self.is_workspace_member(&p.id))
             .collect()
     }
 
+    /// Group the packages by their [`source`](Package::source), e.g. crates.io or a git
+    /// repository.
+    ///
+    /// Packages without a source, i.e. workspace members and other local path
+    /// dependencies, are grouped under `None`.
+    pub fn packages_by_source(&self) -> BTreeMap<Option<Source>, Vec<&Package>> {
+        let mut groups: BTreeMap<Option<Source>, Vec<&Package>> = BTreeMap::new();
+        for package in &self.packages {
+            groups
+                .entry(package.source.clone())
+                .or_default()
+                .push(package);
+        }
+        groups
+    }
+
     /// Get the workspace default packages.
     ///
     /// # Panics
@@ -214,6 +392,374 @@ impl Metadata {
             .filter(|&p| self.workspace_default_members.contains(&p.id))
             .collect()
     }
+
+    /// All packages with the given name, across versions and sources.
+    pub fn packages_named<'a, 'n>(&'a self, name: &'n str) -> impl Iterator<Item = &'a Package> + 'n
+    where
+        'a: 'n,
+    {
+        self.packages.iter().filter(move |pkg| pkg.name == name)
+    }
+
+    /// The package with the given name and version, if any.
+    ///
+    /// If the same version of a package is used from multiple sources, the first one is returned.
+    pub fn package_named_version(&self, name: &str, version: &Version) -> Option<&Package> {
+        self.packages_named(name)
+            .find(|pkg| &pkg.version == version)
+    }
+
+    /// Get the workspace package the file at `path` belongs to.
+    ///
+    /// This is the workspace package whose manifest directory is the closest ancestor of `path`.
+    /// Like the paths reported by cargo, `path` is expected to be absolute.
+    pub fn package_for_path(&self, path: impl AsRef<Path>) -> Option<&Package> {
+        let path = path.as_ref();
+        self.workspace_packages()
+            .into_iter()
+            .filter_map(|package| {
+                let dir = package.manifest_path.parent()?;
+                path.starts_with(dir)
+                    .then(|| (dir.components().count(), package))
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map(|(_, package)| package)
+    }
+
+    /// Get the workspace packages which need to be rebuilt when the file at `path` changes.
+    ///
+    /// These are the package owning the file (see [`Metadata::package_for_path`]) and every
+    /// workspace package depending on it, directly or transitively. Packages are returned in
+    /// the order of [`Metadata::packages`].
+    ///
+    /// If dependencies aren't resolved (see [`MetadataCommand::no_deps`]), only the owning
+    /// package is returned.
+    pub fn packages_affected_by(&self, path: impl AsRef<Path>) -> Vec<&Package> {
+        let Some(owner) = self.package_for_path(path) else {
+            return Vec::new();
+        };
+        let mut affected = BTreeSet::from([&owner.id]);
+        if let Some(resolve) = &self.resolve {
+            let mut pending = vec![&owner.id];
+            while let Some(id) = pending.pop() {
+                for node in &resolve.nodes {
+                    if node.dependencies.contains(id)
+                        && self.is_workspace_member(&node.id)
+                        && affected.insert(&node.id)
+                    {
+                        pending.push(&node.id);
+                    }
+                }
+            }
+        }
+        self.packages
+            .iter()
+            .filter(|package| affected.contains(&package.id))
+            .collect()
+    }
+
+    /// Check that every package id referenced by the workspace members and the resolved
+    /// dependency graph belongs to one of the [`packages`](Metadata::packages).
+    ///
+    /// Output of `cargo metadata` always passes this check, but json assembled by other means
+    /// may not, in which case indexing `Metadata` with such an id panics.
+    pub fn validate(&self) -> std::result::Result<(), Vec<ValidationError>> {
+        let known: BTreeSet<&PackageId> = self.packages.iter().map(|p| &p.id).collect();
+        let mut errors = Vec::new();
+        for id in &self.workspace_members {
+            if !known.contains(id) {
+                errors.push(ValidationError::UnknownWorkspaceMember { id: id.clone() });
+            }
+        }
+        if let Some(resolve) = &self.resolve {
+            if let Some(id) = &resolve.root {
+                if !known.contains(id) {
+                    errors.push(ValidationError::UnknownRoot { id: id.clone() });
+                }
+            }
+            for node in &resolve.nodes {
+                if !known.contains(&node.id) {
+                    errors.push(ValidationError::UnknownNode {
+                        id: node.id.clone(),
+                    });
+                }
+                let dependencies: BTreeSet<&PackageId> = node
+                    .dependencies
+                    .iter()
+                    .chain(node.deps.iter().map(|dep| &dep.pkg))
+                    .collect();
+                for id in dependencies {
+                    if !known.contains(id) {
+                        errors.push(ValidationError::UnknownDependency {
+                            from: node.id.clone(),
+                            to: id.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The version of the [feature resolver](https://doc.rust-lang.org/cargo/reference/resolver.html#resolver-versions)
+    /// used for the workspace, inferred from the edition of the [root package](Metadata::root_package).
+    ///
+    /// `cargo metadata` doesn't report the `resolver` key of the manifest, so this is the
+    /// default cargo picks for the edition: `1` up to edition 2018, `2` for edition 2021 and
+    /// `3` from edition 2024 on. A `resolver` key that overrides the default is not taken into
+    /// account. Virtual workspaces have no root package and don't default to the edition, so
+    /// this returns `None` for them.
+    pub fn resolver_version(&self) -> Option<u8> {
+        let version = match self.root_package()?.edition {
+            Edition::E2015 | Edition::E2018 => 1,
+            Edition::E2021 => 2,
+            Edition::_E2024 | Edition::_E2027 | Edition::_E2030 => 3,
+        };
+        Some(version)
+    }
+
+    /// Removes all packages which aren't workspace members, e.g. to save memory in tools that
+    /// only look at first-party code.
+    ///
+    /// This is lossy: the nodes of the [`resolve`](Metadata::resolve) graph that are
+    /// reachable from the workspace members are kept, but they refer to packages which are no
+    /// longer in [`packages`](Metadata::packages). Indexing the metadata with their ids
+    /// panics, and [`Metadata::validate`] reports them.
+    pub fn prune_to_workspace(&mut self) {
+        let members: BTreeSet<&PackageId> = self.workspace_members.iter().collect();
+        self.packages.retain(|pkg| members.contains(&pkg.id));
+        if let Some(resolve) = &mut self.resolve {
+            let mut reachable = BTreeSet::new();
+            let mut pending: Vec<&PackageId> = members.into_iter().collect();
+            while let Some(id) = pending.pop() {
+                if !reachable.insert(id.clone()) {
+                    continue;
+                }
+                if let Some(node) = resolve.node(id) {
+                    pending.extend(&node.dependencies);
+                }
+            }
+            resolve.nodes.retain(|node| reachable.contains(&node.id));
+        }
+    }
+
+    /// Whether the dependency graph was resolved, i.e. [`resolve`](Metadata::resolve) is set.
+    ///
+    /// It isn't with [`MetadataCommand::no_deps`], in which case the graph-oriented helpers
+    /// like [`Metadata::dependency_graph_roots`] return empty results.
+    pub fn has_resolve(&self) -> bool {
+        self.resolve.is_some()
+    }
+
+    /// Get the ids of the packages no other package in the resolved dependency graph depends on.
+    ///
+    /// For a workspace these are typically the workspace members.
+    ///
+    /// This is empty if dependencies aren't resolved (see [`MetadataCommand::no_deps`]).
+    pub fn dependency_graph_roots(&self) -> Vec<&PackageId> {
+        let Some(resolve) = &self.resolve else {
+            return Vec::new();
+        };
+        let depended_upon: BTreeSet<&PackageId> = resolve
+            .nodes
+            .iter()
+            .flat_map(|node| &node.dependencies)
+            .collect();
+        resolve
+            .nodes
+            .iter()
+            .map(|node| &node.id)
+            .filter(|id| !depended_upon.contains(id))
+            .collect()
+    }
+
+    /// Compare the features enabled in the resolved dependency graph with the ones in `other`.
+    ///
+    /// Every package whose enabled features differ is mapped to the features `other` enables
+    /// in addition (`added`) and the ones it no longer enables (`removed`). Packages which are
+    /// only in one of the graphs count as having no features enabled in the other one.
+    pub fn feature_diff(&self, other: &Metadata) -> BTreeMap<PackageId, FeatureChange> {
+        fn enabled_features(meta: &Metadata) -> BTreeMap<&PackageId, BTreeSet<&str>> {
+            meta.resolve
+                .iter()
+                .flat_map(|resolve| &resolve.nodes)
+                .map(|node| (&node.id, node.features.iter().map(String::as_str).collect()))
+                .collect()
+        }
+
+        let before = enabled_features(self);
+        let after = enabled_features(other);
+        let none = BTreeSet::new();
+        before
+            .keys()
+            .chain(after.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|&id| {
+                let before = before.get(id).unwrap_or(&none);
+                let after = after.get(id).unwrap_or(&none);
+                let change = FeatureChange {
+                    added: after.difference(before).map(|f| f.to_string()).collect(),
+                    removed: before.difference(after).map(|f| f.to_string()).collect(),
+                };
+                (!change.added.is_empty() || !change.removed.is_empty())
+                    .then(|| (id.clone(), change))
+            })
+            .collect()
+    }
+
+    /// Get the features enabled for every package of the resolved dependency graph.
+    ///
+    /// This is empty if dependencies aren't resolved (see [`MetadataCommand::no_deps`]).
+    pub fn all_enabled_features(&self) -> BTreeMap<&PackageId, &[String]> {
+        self.resolve
+            .iter()
+            .flat_map(|resolve| &resolve.nodes)
+            .map(|node| (&node.id, node.features.as_slice()))
+            .collect()
+    }
+
+    /// A hash of the features enabled for every package of the resolved dependency graph,
+    /// e.g. to use as a build cache key.
+    ///
+    /// The fingerprint only depends on the name, version and source of each package and on
+    /// its enabled features, not on their order, and is the same across runs and platforms.
+    /// Packages without a source, i.e. workspace members and other path dependencies, are
+    /// hashed without their path, so checkouts in different locations get the same
+    /// fingerprint.
+    pub fn feature_fingerprint(&self) -> String {
+        // 64 bit FNV-1a, since the hashers of `std` don't guarantee stable output
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        let lookup = PackageLookup::new(self);
+        let packages: BTreeSet<_> = self
+            .all_enabled_features()
+            .into_iter()
+            .map(|(id, features)| {
+                let key = match lookup.get(id) {
+                    Some(pkg) => {
+                        let source = pkg.source.as_ref().map_or("path", |s| s.repr.as_str());
+                        format!("{} {} {}", pkg.name, pkg.version, source)
+                    }
+                    None => id.repr.clone(),
+                };
+                (key, features.iter().collect::<BTreeSet<_>>())
+            })
+            .collect();
+        for (key, features) in packages {
+            write(key.as_bytes());
+            write(&[0xff]);
+            for feature in features {
+                write(feature.as_bytes());
+                write(&[0]);
+            }
+            write(&[0xfe]);
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// Get every direct dependency edge of the resolved dependency graph.
+    ///
+    /// Each edge joins a [`NodeDep`] of the resolve graph with the [`Dependency`] declared in
+    /// the depending package's manifest, so renamed dependencies are handled. A dependency which
+    /// is used as more than one kind (e.g. both normal and build) produces one edge per kind.
+    ///
+    /// This is empty if dependencies aren't resolved (see [`MetadataCommand::no_deps`]), and
+    /// requires Cargo 1.41 or newer, which added [`NodeDep::dep_kinds`]. Edges from or to
+    /// packages that aren't in [`packages`](Metadata::packages), e.g. after
+    /// [`Metadata::prune_to_workspace`], are skipped.
+    pub fn dependency_edges(&self) -> Vec<DependencyEdge> {
+        let Some(resolve) = &self.resolve else {
+            return Vec::new();
+        };
+        let lookup = PackageLookup::new(self);
+        let mut edges = Vec::new();
+        for node in &resolve.nodes {
+            let Some(from) = lookup.get(&node.id) else {
+                continue;
+            };
+            for dep in &node.deps {
+                let Some(to) = lookup.get(&dep.pkg) else {
+                    continue;
+                };
+                for dep_kind in &dep.dep_kinds {
+                    let features = from
+                        .dependencies
+                        .iter()
+                        .find(|declared| declares_node_dep(declared, to, dep, dep_kind))
+                        .map(|declared| declared.features.clone())
+                        .unwrap_or_default();
+                    edges.push(DependencyEdge {
+                        from: node.id.clone(),
+                        to: dep.pkg.clone(),
+                        kind: dep_kind.kind,
+                        features,
+                    });
+                }
+            }
+        }
+        edges
+    }
+}
+
+/// Whether `declared` is the manifest entry that resolved to the package `to` via `dep`.
+fn declares_node_dep(
+    declared: &Dependency,
+    to: &Package,
+    dep: &NodeDep,
+    dep_kind: &DepKindInfo,
+) -> bool {
+    // `NodeDep::name` is the name of the library target, so dashes become underscores
+    let same_name = match &declared.rename {
+        Some(rename) => rename.replace('-', "_") == dep.name,
+        None => {
+            let lib_name = to.lib_target().map_or(&to.name, |lib| &lib.name);
+            lib_name.replace('-', "_") == dep.name
+        }
+    };
+    same_name
+        && declared.name == to.name
+        // `*`, e.g. for path dependencies without a version, also allows pre-releases
+        && (declared.req == VersionReq::STAR || declared.req.matches(&to.version))
+        && declared.kind == dep_kind.kind
+        && declared.target == dep_kind.target
+}
+
+/// How the enabled features of a package changed between two resolved dependency graphs.
+///
+/// See [`Metadata::feature_diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct FeatureChange {
+    /// Features which are only enabled in the newer graph
+    pub added: Vec<String>,
+    /// Features which are only enabled in the older graph
+    pub removed: Vec<String>,
+}
+
+/// A direct dependency from one package on another in the resolved dependency graph.
+///
+/// See [`Metadata::dependency_edges`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct DependencyEdge {
+    /// The depending package
+    pub from: PackageId,
+    /// The package depended upon
+    pub to: PackageId,
+    /// The kind of dependency
+    pub kind: DependencyKind,
+    /// The features enabled for this dependency in the manifest of the depending package
+    pub features: Vec<String>,
 }
 
 impl<'a> std::ops::Index<&'a PackageId> for Metadata {
@@ -227,6 +773,45 @@ impl<'a> std::ops::Index<&'a PackageId> for Metadata {
     }
 }
 
+/// An index of the packages of a [`Metadata`] for repeated lookups.
+///
+/// Indexing [`Metadata`] by [`PackageId`] scans the list of packages, so for many lookups,
+/// e.g. while walking the whole dependency graph, it's cheaper to build a `PackageLookup` once.
+#[derive(Clone, Debug)]
+pub struct PackageLookup<'a> {
+    by_id: BTreeMap<&'a PackageId, &'a Package>,
+    by_name: BTreeMap<&'a str, Vec<&'a Package>>,
+}
+
+impl<'a> PackageLookup<'a> {
+    /// Index the packages of `metadata`.
+    pub fn new(metadata: &'a Metadata) -> Self {
+        let mut lookup = PackageLookup {
+            by_id: BTreeMap::new(),
+            by_name: BTreeMap::new(),
+        };
+        for package in &metadata.packages {
+            lookup.by_id.insert(&package.id, package);
+            lookup
+                .by_name
+                .entry(&package.name)
+                .or_default()
+                .push(package);
+        }
+        lookup
+    }
+
+    /// Get the package with the given id.
+    pub fn get(&self, id: &PackageId) -> Option<&'a Package> {
+        self.by_id.get(id).copied()
+    }
+
+    /// Get all packages with the given name, e.g. several versions of the same crate.
+    pub fn get_by_name(&self, name: &str) -> &[&'a Package] {
+        self.by_name.get(name).map_or(&[], Vec::as_slice)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Default)]
 #[serde(transparent)]
 /// A list of default workspace members.
@@ -286,16 +871,54 @@ pub struct Resolve {
     pub nodes: Vec<Node>,
 
     /// The crate for which the metadata was read.
+    #[cfg_attr(feature = "builder", builder(default))]
     pub root: Option<PackageId>,
 }
 
+impl Resolve {
+    /// Get the node of the package with the given id, if it is part of the graph.
+    pub fn node(&self, id: &PackageId) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == *id)
+    }
+
+    /// The features enabled for the package with the given id in the resolved graph.
+    ///
+    /// Returns an empty slice if the package is not part of the graph.
+    pub fn enabled_features(&self, id: &PackageId) -> &[String] {
+        self.node(id).map_or(&[], |node| &node.features)
+    }
+
+    /// The packages that directly depend on the package with the given id.
+    pub fn dependents(&self, id: &PackageId) -> Vec<&PackageId> {
+        self.nodes
+            .iter()
+            .filter(|node| node.dependencies.contains(id))
+            .map(|node| &node.id)
+            .collect()
+    }
+
+    /// The packages that directly or indirectly depend on the package with the given id.
+    ///
+    /// The package itself is only included if it is part of a dependency cycle.
+    pub fn transitive_dependents(&self, id: &PackageId) -> BTreeSet<PackageId> {
+        let mut dependents = BTreeSet::new();
+        let mut queue = vec![id];
+        while let Some(id) = queue.pop() {
+            for dependent in self.dependents(id) {
+                if dependents.insert(dependent.clone()) {
+                    queue.push(dependent);
+                }
+            }
+        }
+        dependents
+    }
+}
+
 impl<'a> std::ops::Index<&'a PackageId> for Resolve {
     type Output = Node;
 
     fn index(&self, idx: &'a PackageId) -> &Self::Output {
-        self.nodes
-            .iter()
-            .find(|p| p.id == *idx)
+        self.node(idx)
             .unwrap_or_else(|| panic!("no Node with this id: {:?}", idx))
     }
 }
@@ -312,6 +935,7 @@ pub struct Node {
     ///
     /// `deps` handles renamed dependencies whereas `dependencies` does not.
     #[serde(default)]
+    #[cfg_attr(feature = "builder", builder(default))]
     pub deps: Vec<NodeDep>,
 
     /// List of opaque identifiers for this node's dependencies.
@@ -320,9 +944,50 @@ pub struct Node {
 
     /// Features enabled on the crate
     #[serde(default)]
+    #[cfg_attr(feature = "builder", builder(default))]
     pub features: Vec<String>,
 }
 
+impl Node {
+    /// The features of the dependency `dep` of this node that are enabled by this edge.
+    ///
+    /// `cargo metadata` only reports the features of a package after unifying the requests
+    /// of all its dependents, so this is an approximation: the features declared for the
+    /// dependency in the manifest of this node's package, plus `default` unless default
+    /// features are disabled, expanded through the dependency's `[features]` table and
+    /// limited to the features enabled in the resolved graph.
+    ///
+    /// This is empty if the packages or the declaration can't be found, e.g. with cargo
+    /// versions older than 1.41, which don't report [`NodeDep::dep_kinds`].
+    pub fn features_for_dep(&self, dep: &NodeDep, metadata: &Metadata) -> Vec<String> {
+        let package = |id: &PackageId| metadata.packages.iter().find(|pkg| pkg.id == *id);
+        let (Some(from), Some(to)) = (package(&self.id), package(&dep.pkg)) else {
+            return Vec::new();
+        };
+        let resolved = metadata
+            .resolve
+            .as_ref()
+            .map_or(&[][..], |resolve| resolve.enabled_features(&dep.pkg));
+        let mut requested = BTreeSet::new();
+        for dep_kind in &dep.dep_kinds {
+            let declared = from
+                .dependencies
+                .iter()
+                .filter(|declared| declares_node_dep(declared, to, dep, dep_kind));
+            for declared in declared {
+                let default = declared.uses_default_features.then_some("default");
+                for feature in declared.features.iter().map(String::as_str).chain(default) {
+                    requested.extend(to.enabled_features_closure(feature));
+                }
+            }
+        }
+        requested
+            .into_iter()
+            .filter(|feature| resolved.contains(feature))
+            .collect()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "builder", derive(Builder))]
 #[non_exhaustive]
@@ -341,6 +1006,16 @@ pub struct NodeDep {
     pub dep_kinds: Vec<DepKindInfo>,
 }
 
+impl NodeDep {
+    /// Return true if this is a normal dependency on at least one platform,
+    /// as opposed to e.g. only a dev-dependency.
+    pub fn is_normal(&self) -> bool {
+        self.dep_kinds
+            .iter()
+            .any(|info| info.kind == DependencyKind::Normal)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "builder", derive(Builder))]
 #[non_exhaustive]
@@ -531,66 +1206,320 @@ impl Package {
                 .join(file)
         })
     }
-}
 
-/// The source of a package such as crates.io.
-///
-/// It is possible to inspect the `repr` field, if the need arises, but its
-/// precise format is an implementation detail and is subject to change.
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct Source {
-    /// The underlying string representation of a source.
-    pub repr: String,
-}
+    /// The paths of the main source files of all targets of this package.
+    pub fn source_roots(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.targets.iter().map(|target| target.src_path.as_path())
+    }
 
-impl Source {
-    /// Returns true if the source is crates.io.
-    pub fn is_crates_io(&self) -> bool {
-        self.repr == "registry+https://github.com/rust-lang/crates.io-index"
+    /// The library target of this package, if it has one.
+    ///
+    /// This is the target of kind `lib`, `rlib`, `dylib`, `cdylib`, `staticlib` or `proc-macro`.
+    pub fn lib_target(&self) -> Option<&Target> {
+        self.targets.iter().find(|target| target.is_library())
     }
-}
 
-impl fmt::Display for Source {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.repr, f)
+    /// Whether this package is a proc-macro crate, i.e. its library target is a proc macro.
+    ///
+    /// Proc macros are compiled for the host, even when cross-compiling.
+    pub fn is_proc_macro(&self) -> bool {
+        self.targets.iter().any(Target::is_proc_macro)
     }
-}
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
-#[cfg_attr(feature = "builder", derive(Builder))]
-#[cfg_attr(feature = "builder", builder(pattern = "owned", setter(into)))]
-#[non_exhaustive]
-/// A single target (lib, bin, example, ...) provided by a crate
-pub struct Target {
-    /// Name as given in the `Cargo.toml` or generated from the file name
-    pub name: String,
-    /// Kind of target.
+    /// The binary targets of this package.
+    pub fn bin_targets(&self) -> impl Iterator<Item = &Target> {
+        self.targets.iter().filter(|target| target.is_bin())
+    }
+
+    /// The dependencies of this package that are of the given `kind`.
+    pub fn dependencies_of_kind(&self, kind: DependencyKind) -> impl Iterator<Item = &Dependency> {
+        self.dependencies.iter().filter(move |dep| dep.kind == kind)
+    }
+
+    /// The `[dependencies]` of this package.
+    pub fn normal_dependencies(&self) -> impl Iterator<Item = &Dependency> {
+        self.dependencies_of_kind(DependencyKind::Normal)
+    }
+
+    /// The `[dev-dependencies]` of this package.
+    pub fn dev_dependencies(&self) -> impl Iterator<Item = &Dependency> {
+        self.dependencies_of_kind(DependencyKind::Development)
+    }
+
+    /// The `[build-dependencies]` of this package.
+    pub fn build_dependencies(&self) -> impl Iterator<Item = &Dependency> {
+        self.dependencies_of_kind(DependencyKind::Build)
+    }
+
+    /// The dependencies of this package which are used when building for the target `triple`,
+    /// see [`Dependency::applies_to`].
+    pub fn dependencies_for_target(
+        &self,
+        triple: &str,
+        cfgs: &[cargo_platform::Cfg],
+    ) -> Vec<&Dependency> {
+        self.dependencies
+            .iter()
+            .filter(|dep| dep.applies_to(cfgs, triple))
+            .collect()
+    }
+
+    /// The dependencies of this package sorted by kind, name and version requirement, for
+    /// output that doesn't depend on the order of the manifest.
+    pub fn sorted_dependencies(&self) -> Vec<&Dependency> {
+        let mut dependencies: Vec<&Dependency> = self.dependencies.iter().collect();
+        dependencies.sort_by(|a, b| {
+            (a.kind, &a.name)
+                .cmp(&(b.kind, &b.name))
+                .then_with(|| a.req.to_string().cmp(&b.req.to_string()))
+        });
+        dependencies
+    }
+
+    /// Compares packages by name and then by version, for deterministic reports.
     ///
-    /// The possible values are `example`, `test`, `bench`, `custom-build` and
-    /// [Cargo crate types](https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-crate-type-field):
-    /// `bin`, `lib`, `rlib`, `dylib`, `cdylib`, `staticlib`, `proc-macro`.
+    /// `Package` doesn't implement `Ord` because its `PartialEq` compares all fields, and
+    /// two packages with the same name and version (e.g. from different sources) compare
+    /// as `Ordering::Equal` here. Use it with `sort_by`:
     ///
-    /// Other possible values may be added in the future.
-    pub kind: Vec<TargetKind>,
-    /// Similar to `kind`, but only reports the
-    /// [Cargo crate types](https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-crate-type-field):
-    /// `bin`, `lib`, `rlib`, `dylib`, `cdylib`, `staticlib`, `proc-macro`.
-    /// Everything that's not a proc macro or a library of some kind is reported as "bin".
+    /// ```
+    /// # fn sort(packages: &mut [cargo_metadata::Package]) {
+    /// packages.sort_by(cargo_metadata::Package::cmp_by_name_version);
+    /// # }
+    /// ```
+    pub fn cmp_by_name_version(&self, other: &Package) -> Ordering {
+        (&self.name, &self.version).cmp(&(&other.name, &other.version))
+    }
+
+    /// The features transitively enabled by `feature`, including `feature` itself.
     ///
-    /// Other possible values may be added in the future.
-    #[serde(default)]
-    #[cfg_attr(feature = "builder", builder(default))]
-    pub crate_types: Vec<CrateType>,
+    /// Only features of this package are followed: `dep:name`, `name/feature` and
+    /// `name?/feature` values enable features of dependencies and are ignored.
+    pub fn enabled_features_closure(&self, feature: &str) -> BTreeSet<String> {
+        let mut enabled = BTreeSet::new();
+        let mut pending = vec![feature];
+        while let Some(feature) = pending.pop() {
+            if !enabled.insert(feature.to_owned()) {
+                // already visited, which also breaks cycles between features
+                continue;
+            }
+            if let Some(values) = self.features.get(feature) {
+                pending.extend(
+                    values
+                        .iter()
+                        .map(String::as_str)
+                        .filter(|value| matches!(value.parse(), Ok(FeatureValue::Feature(_)))),
+                );
+            }
+        }
+        enabled
+    }
 
-    #[serde(default)]
-    #[cfg_attr(feature = "builder", builder(default))]
-    #[serde(rename = "required-features")]
-    /// This target is built only if these features are enabled.
-    /// It doesn't apply to `lib` targets.
-    pub required_features: Vec<String>,
-    /// Path to the main source file of the target
-    pub src_path: Utf8PathBuf,
+    /// The names of the optional dependencies that implicitly define a feature of the
+    /// same name.
+    ///
+    /// Up to edition 2021, an optional dependency `foo` that no feature refers to as
+    /// `dep:foo` implicitly defines a `foo = ["dep:foo"]` feature. Since edition 2024,
+    /// optional dependencies no longer define implicit features.
+    ///
+    /// Starting with Rust 1.60, cargo already lists implicit features in
+    /// [`features`](Package::features). Such an entry is not considered a `dep:` reference.
+    pub fn implicit_optional_dep_features(&self) -> BTreeSet<String> {
+        if self.edition >= Edition::_E2024 {
+            return BTreeSet::new();
+        }
+        self.dependencies
+            .iter()
+            .filter(|dep| dep.optional)
+            .map(|dep| dep.rename.as_deref().unwrap_or(&dep.name))
+            .filter(|name| {
+                let dep_value = format!("dep:{}", name);
+                !self.features.iter().any(|(feature, values)| {
+                    let is_implicit = feature == name && values.len() == 1;
+                    !is_implicit && values.contains(&dep_value)
+                })
+            })
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Deserializes the [`metadata`](Package::metadata) of the package into `T`.
+    ///
+    /// Returns `Ok(None)` if the package has no `package.metadata` section.
+    pub fn metadata_typed<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> std::result::Result<Option<T>, serde_json::Error> {
+        if self.metadata.is_null() {
+            return Ok(None);
+        }
+        T::deserialize(&self.metadata).map(Some)
+    }
+
+    /// Get the `package.metadata.<key>` table of the package, if it exists.
+    pub fn metadata_table(&self, key: &str) -> Option<&serde_json::Value> {
+        self.metadata.get(key)
+    }
+
+    /// The license identifiers in the [`license`](Package::license) expression, in the order
+    /// they appear and without duplicates, e.g. `MIT` and `Apache-2.0` for `MIT OR Apache-2.0`.
+    ///
+    /// The expression is only split on parentheses and the `OR`, `AND` and `WITH` operators
+    /// (and the deprecated `/` separator), it isn't validated. Exceptions following `WITH`
+    /// are not license identifiers and are skipped.
+    pub fn license_ids(&self) -> Vec<String> {
+        let Some(license) = &self.license else {
+            return Vec::new();
+        };
+        let mut ids: Vec<String> = Vec::new();
+        let mut tokens = license
+            .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+            .filter(|token| !token.is_empty());
+        while let Some(token) = tokens.next() {
+            match token {
+                "OR" | "AND" => {}
+                "WITH" => {
+                    tokens.next();
+                }
+                id => {
+                    if !ids.iter().any(|known| known == id) {
+                        ids.push(id.to_owned());
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// Whether `id` is one of the [`license_ids`](Package::license_ids) of this package.
+    pub fn has_license(&self, id: &str) -> bool {
+        self.license_ids().iter().any(|license| license == id)
+    }
+
+    /// The page of this exact package version on crates.io.
+    ///
+    /// This is `None` unless the package comes from crates.io.
+    pub fn crates_io_url(&self) -> Option<String> {
+        self.source
+            .as_ref()
+            .filter(|source| source.is_crates_io())
+            .map(|_| format!("https://crates.io/crates/{}/{}", self.name, self.version))
+    }
+
+    /// The distinct editions used by the targets of this package.
+    ///
+    /// This contains more than one edition if some targets override the package's
+    /// [`edition`](Package::edition).
+    pub fn target_editions(&self) -> BTreeSet<Edition> {
+        self.targets.iter().map(|target| target.edition).collect()
+    }
+
+    /// The binary target `cargo run` picks when no `--bin` is given.
+    ///
+    /// This is the binary named by [`default_run`](Package::default_run) if set,
+    /// otherwise the package's only binary target.
+    pub fn default_binary(&self) -> std::result::Result<&Target, RunSelectionError> {
+        let mut bins = self.targets.iter().filter(|target| target.is_bin());
+        if let Some(name) = &self.default_run {
+            return bins
+                .find(|target| &target.name == name)
+                .ok_or_else(|| RunSelectionError::DefaultRunNotFound { name: name.clone() });
+        }
+        match (bins.next(), bins.next()) {
+            (None, _) => Err(RunSelectionError::NoBinaries),
+            (Some(bin), None) => Ok(bin),
+            (Some(_), Some(_)) => Err(RunSelectionError::Ambiguous {
+                candidates: self
+                    .targets
+                    .iter()
+                    .filter(|target| target.is_bin())
+                    .map(|target| target.name.clone())
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// The source of packages from crates.io.
+const CRATES_IO_INDEX: &str = "registry+https://github.com/rust-lang/crates.io-index";
+/// The source of packages from crates.io when using its sparse index.
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+
+/// Whether the source string `source` is crates.io, through either of its indices.
+pub(crate) fn is_crates_io_source(source: &str) -> bool {
+    source == CRATES_IO_INDEX || source == CRATES_IO_SPARSE_INDEX
+}
+
+/// The index URL of a registry source string, without its `registry+` or `sparse+` prefix.
+pub(crate) fn registry_source_url(source: &str) -> Option<&str> {
+    source
+        .strip_prefix("registry+")
+        .or_else(|| source.strip_prefix("sparse+"))
+}
+
+/// The source of a package such as crates.io.
+///
+/// It is possible to inspect the `repr` field, if the need arises, but its
+/// precise format is an implementation detail and is subject to change.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Source {
+    /// The underlying string representation of a source.
+    pub repr: String,
+}
+
+impl Source {
+    /// Returns true if the source is crates.io, through either its git or its sparse index.
+    pub fn is_crates_io(&self) -> bool {
+        is_crates_io_source(&self.repr)
+    }
+
+    /// The URL of the index if the source is a registry, without the `registry+` or
+    /// `sparse+` prefix, e.g. `https://index.crates.io/`.
+    pub fn registry_url(&self) -> Option<&str> {
+        registry_source_url(&self.repr)
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.repr, f)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "builder", derive(Builder))]
+#[cfg_attr(feature = "builder", builder(pattern = "owned", setter(into)))]
+#[non_exhaustive]
+/// A single target (lib, bin, example, ...) provided by a crate
+pub struct Target {
+    /// Name as given in the `Cargo.toml` or generated from the file name
+    pub name: String,
+    /// Kind of target.
+    ///
+    /// The possible values are `example`, `test`, `bench`, `custom-build` and
+    /// [Cargo crate types](https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-crate-type-field):
+    /// `bin`, `lib`, `rlib`, `dylib`, `cdylib`, `staticlib`, `proc-macro`.
+    ///
+    /// Other possible values may be added in the future.
+    pub kind: Vec<TargetKind>,
+    /// Similar to `kind`, but only reports the
+    /// [Cargo crate types](https://doc.rust-lang.org/cargo/reference/cargo-targets.html#the-crate-type-field):
+    /// `bin`, `lib`, `rlib`, `dylib`, `cdylib`, `staticlib`, `proc-macro`.
+    /// Everything that's not a proc macro or a library of some kind is reported as "bin".
+    ///
+    /// Other possible values may be added in the future.
+    #[serde(default)]
+    #[cfg_attr(feature = "builder", builder(default))]
+    pub crate_types: Vec<CrateType>,
+
+    #[serde(default)]
+    #[cfg_attr(feature = "builder", builder(default))]
+    #[serde(rename = "required-features")]
+    /// This target is built only if these features are enabled.
+    /// It doesn't apply to `lib` targets.
+    pub required_features: Vec<String>,
+    /// Path to the main source file of the target
+    pub src_path: Utf8PathBuf,
     /// Rust edition for this target
     #[serde(default)]
     #[cfg_attr(feature = "builder", builder(default))]
@@ -627,12 +1556,42 @@ macro_rules! methods_target_is_kind {
     }
 }
 
+macro_rules! methods_target_produces {
+    ($($name:ident => $crate_type:expr),*) => {
+        $(
+            /// Return true if this target produces crate type `$crate_type`.
+            pub fn $name(&self) -> bool {
+                self.produces($crate_type)
+            }
+        )*
+    }
+}
+
 impl Target {
     /// Return true if this target is of the given kind.
     pub fn is_kind(&self, name: TargetKind) -> bool {
         self.kind.iter().any(|kind| kind == &name)
     }
 
+    /// Return true if `cargo test` runs the doc tests of this target.
+    ///
+    /// Only library targets, including proc macros and libraries with several crate types,
+    /// have doc tests. `doctest` alone isn't enough, since it is always `true` with cargo
+    /// versions older than 1.37.
+    pub fn runs_doctests(&self) -> bool {
+        self.doctest && self.is_library()
+    }
+
+    /// Whether this is a library target of any crate type, see [`Package::lib_target`].
+    fn is_library(&self) -> bool {
+        self.is_lib()
+            || self.is_rlib()
+            || self.is_dylib()
+            || self.is_cdylib()
+            || self.is_staticlib()
+            || self.is_proc_macro()
+    }
+
     // Generate `is_*` methods for each `TargetKind`
     methods_target_is_kind! {
         is_lib => TargetKind::Lib,
@@ -647,6 +1606,22 @@ impl Target {
         is_rlib => TargetKind::RLib,
         is_staticlib => TargetKind::StaticLib
     }
+
+    /// Return true if this target produces the given crate type.
+    pub fn produces(&self, crate_type: CrateType) -> bool {
+        self.crate_types.contains(&crate_type)
+    }
+
+    // Generate `produces_*` methods for each `CrateType`
+    methods_target_produces! {
+        produces_bin => CrateType::Bin,
+        produces_cdylib => CrateType::CDyLib,
+        produces_dylib => CrateType::DyLib,
+        produces_lib => CrateType::Lib,
+        produces_proc_macro => CrateType::ProcMacro,
+        produces_rlib => CrateType::RLib,
+        produces_staticlib => CrateType::StaticLib
+    }
 }
 
 /// Kind of target.
@@ -820,10 +1795,13 @@ impl fmt::Display for CrateType {
 /// The Rust edition
 ///
 /// As of writing this comment rust editions 2024, 2027 and 2030 are not actually a thing yet but are parsed nonetheless for future proofing.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
 #[non_exhaustive]
 pub enum Edition {
     /// Edition 2015
+    #[default]
     #[serde(rename = "2015")]
     E2015,
     /// Edition 2018
@@ -864,12 +1842,6 @@ impl fmt::Display for Edition {
     }
 }
 
-impl Default for Edition {
-    fn default() -> Self {
-        Self::E2015
-    }
-}
-
 fn default_true() -> bool {
     true
 }
@@ -898,12 +1870,8 @@ pub struct MetadataCommand {
     current_dir: Option<PathBuf>,
     /// Output information only about workspace members and don't fetch dependencies.
     no_deps: bool,
-    /// Collections of `CargoOpt::SomeFeatures(..)`
-    features: Vec<String>,
-    /// Latched `CargoOpt::AllFeatures`
-    all_features: bool,
-    /// Latched `CargoOpt::NoDefaultFeatures`
-    no_default_features: bool,
+    /// Feature flags to pass to `cargo`
+    features: FeatureFlags,
     /// Arbitrary command line flags to pass to `cargo`.  These will be added
     /// to the end of the command line invocation.
     other_options: Vec<String>,
@@ -912,6 +1880,20 @@ pub struct MetadataCommand {
     env: BTreeMap<OsString, OsString>,
     /// Show stderr
     verbose: bool,
+    /// Number of `-v` flags to pass to `cargo`.
+    verbosity: u8,
+    /// Rustup toolchain to run `cargo` with.
+    toolchain: Option<String>,
+    /// The `--format-version` to request. Defaults to 1.
+    format_version: Option<usize>,
+    /// Reject fields of the output that this crate doesn't know about.
+    strict: bool,
+    /// Only keep the packages with this name.
+    package: Option<String>,
+    /// Values to pass to `cargo` with `--config`.
+    config: Vec<String>,
+    /// How long to wait for `cargo` before killing it.
+    timeout: Option<Duration>,
 }
 
 impl MetadataCommand {
@@ -981,23 +1963,7 @@ impl MetadataCommand {
     ///     # ;
     /// ```
     pub fn features(&mut self, features: CargoOpt) -> &mut MetadataCommand {
-        match features {
-            CargoOpt::SomeFeatures(features) => self.features.extend(features),
-            CargoOpt::NoDefaultFeatures => {
-                assert!(
-                    !self.no_default_features,
-                    "Do not supply CargoOpt::NoDefaultFeatures more than once!"
-                );
-                self.no_default_features = true;
-            }
-            CargoOpt::AllFeatures => {
-                assert!(
-                    !self.all_features,
-                    "Do not supply CargoOpt::AllFeatures more than once!"
-                );
-                self.all_features = true;
-            }
-        }
+        self.features.add(features);
         self
     }
     /// Arbitrary command line flags to pass to `cargo`.  These will be added
@@ -1039,16 +2005,109 @@ impl MetadataCommand {
         self
     }
 
+    /// Passes `level` times `-v` to `cargo`, e.g. `-v -v` for a level of 2.
+    ///
+    /// This mostly affects dependency resolution and registry access, so it can help to
+    /// diagnose why `cargo metadata` failed: the additional output ends up in the `stderr`
+    /// of [`Error::CargoMetadata`].
+    pub fn verbosity(&mut self, level: u8) -> &mut MetadataCommand {
+        self.verbosity = level;
+        self
+    }
+
+    /// Rustup toolchain to run `cargo` with, e.g. `stable` or `nightly-2024-01-01`.
+    ///
+    /// This passes `+<toolchain>` to `cargo`, which is only understood by the `cargo` proxy
+    /// installed by [rustup](https://rust-lang.github.io/rustup/). The `$CARGO` environment
+    /// variable is ignored when a toolchain is set, since it points at the `cargo` of one
+    /// specific toolchain rather than at the proxy.
+    ///
+    /// ```no_run
+    /// # use cargo_metadata::MetadataCommand;
+    /// MetadataCommand::new()
+    ///     .toolchain("nightly")
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn toolchain(&mut self, name: impl Into<String>) -> &mut MetadataCommand {
+        self.toolchain = Some(name.into());
+        self
+    }
+
+    /// Requests a specific `--format-version` of the `cargo metadata` output.
+    /// Defaults to `1`.
+    ///
+    /// This crate only models the shape of format version 1. Parsing the output of a
+    /// version whose shape differs may fail, so only change this if you know the version
+    /// is compatible. The version that was returned is available through
+    /// [`Metadata::format_version`].
+    pub fn format_version(&mut self, version: usize) -> &mut MetadataCommand {
+        self.format_version = Some(version);
+        self
+    }
+
+    /// Fail with [`Error::UnknownFields`] if the output contains fields that this crate
+    /// doesn't know about, instead of silently ignoring them.
+    ///
+    /// This is meant as a debugging aid, e.g. to check whether a newer `cargo` emits
+    /// information that is not exposed by this crate yet. Don't enable it in tools that
+    /// should keep working with future versions of `cargo`. See [`MetadataCommand::parse_strict`].
+    pub fn strict(&mut self) -> &mut MetadataCommand {
+        self.strict = true;
+        self
+    }
+
+    /// Overrides a configuration value for this invocation by passing `--config <value>`
+    /// to `cargo`. `value` is either a `KEY=VALUE` pair in TOML syntax or the path to an
+    /// additional configuration file. Call this multiple times to pass multiple overrides.
+    ///
+    /// This requires cargo 1.63 or newer.
+    ///
+    /// ```no_run
+    /// # use cargo_metadata::MetadataCommand;
+    /// MetadataCommand::new()
+    ///     .config("net.offline=true")
+    ///     .config("registries.my-registry.index='https://example.com/index'")
+    ///     // ...
+    ///     # ;
+    /// ```
+    pub fn config(&mut self, value: impl Into<String>) -> &mut MetadataCommand {
+        self.config.push(value.into());
+        self
+    }
+
+    /// Kill `cargo` and fail with [`Error::Timeout`] if it doesn't finish within `timeout`,
+    /// e.g. because it is stuck on a stalled network connection or waiting for a lock.
+    ///
+    /// Without a timeout, `exec` waits for `cargo` indefinitely.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut MetadataCommand {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Only keep the packages named `name` in [`Metadata::packages`].
+    ///
+    /// All versions of the package are kept if there are multiple. `exec` fails with
+    /// [`Error::PackageNotFound`] if there is no package with that name. Combine this
+    /// with [`no_deps`](MetadataCommand::no_deps) to select a workspace member without
+    /// knowing the path of its manifest.
+    ///
+    /// Only the list of packages is filtered, the other fields like
+    /// [`Metadata::workspace_members`] and [`Metadata::resolve`] are left as they are.
+    pub fn package(&mut self, name: impl Into<String>) -> &mut MetadataCommand {
+        self.package = Some(name.into());
+        self
+    }
+
     /// Builds a command for `cargo metadata`.  This is the first
     /// part of the work of `exec`.
+    ///
+    /// The command isn't run, so it can also be used to log or inspect the exact
+    /// invocation, e.g. through [`Command::get_args`].
     pub fn cargo_command(&self) -> Command {
-        let cargo = self
-            .cargo_path
-            .clone()
-            .or_else(|| env::var("CARGO").map(PathBuf::from).ok())
-            .unwrap_or_else(|| PathBuf::from("cargo"));
-        let mut cmd = Command::new(cargo);
-        cmd.args(["metadata", "--format-version", "1"]);
+        let mut cmd = new_cargo_command(self.cargo_path.as_ref(), self.toolchain.as_deref());
+        cmd.args(["metadata", "--format-version"])
+            .arg(self.format_version.unwrap_or(1).to_string());
 
         if self.no_deps {
             cmd.arg("--no-deps");
@@ -1058,19 +2117,17 @@ impl MetadataCommand {
             cmd.current_dir(path);
         }
 
-        if !self.features.is_empty() {
-            cmd.arg("--features").arg(self.features.join(","));
-        }
-        if self.all_features {
-            cmd.arg("--all-features");
-        }
-        if self.no_default_features {
-            cmd.arg("--no-default-features");
-        }
+        self.features.apply(&mut cmd);
 
         if let Some(manifest_path) = &self.manifest_path {
             cmd.arg("--manifest-path").arg(manifest_path.as_os_str());
         }
+        for _ in 0..self.verbosity {
+            cmd.arg("-v");
+        }
+        for value in &self.config {
+            cmd.arg("--config").arg(value);
+        }
         cmd.args(&self.other_options);
 
         cmd.envs(&self.env);
@@ -1085,26 +2142,384 @@ impl MetadataCommand {
         Ok(meta)
     }
 
+    /// Parses `cargo metadata` output like [`MetadataCommand::parse`], but fails with
+    /// [`Error::UnknownFields`] if `data` contains fields that aren't part of [`Metadata`].
+    pub fn parse_strict<T: AsRef<str>>(data: T) -> Result<Metadata> {
+        let mut unknown = Vec::new();
+        let mut deserializer = serde_json::Deserializer::from_str(data.as_ref());
+        let meta =
+            serde_ignored::deserialize(&mut deserializer, |path| unknown.push(path.to_string()))?;
+        deserializer.end()?;
+        if !unknown.is_empty() {
+            return Err(Error::UnknownFields { paths: unknown });
+        }
+        Ok(meta)
+    }
+
     /// Runs configured `cargo metadata` and returns parsed `Metadata`.
     pub fn exec(&self) -> Result<Metadata> {
+        let output = self.output()?;
+        self.parse_output(&output).map(|(meta, _)| meta)
+    }
+
+    /// Runs configured `cargo metadata` and returns the parsed `Metadata` along with the
+    /// json it was parsed from, e.g. to cache it.
+    ///
+    /// The json is returned as `cargo` printed it, so it isn't affected by
+    /// [`MetadataCommand::package`].
+    pub fn exec_raw(&self) -> Result<(Metadata, String)> {
+        let output = self.output()?;
+        self.parse_output(&output)
+            .map(|(meta, json)| (meta, json.to_owned()))
+    }
+
+    /// Runs configured `cargo metadata`, the shared head of `exec` and `exec_raw`.
+    fn output(&self) -> Result<Output> {
         let mut command = self.cargo_command();
         if self.verbose {
             command.stderr(Stdio::inherit());
+        } else {
+            command.stderr(Stdio::piped());
         }
-        let output = command.output()?;
+        let output = match self.timeout {
+            Some(timeout) => output_with_timeout(&mut command, timeout)?,
+            None => command.output()?,
+        };
+        Ok(output)
+    }
+
+    /// Like [`MetadataCommand::exec`], but awaits `cargo metadata` on the tokio runtime
+    /// instead of blocking the current thread.
+    ///
+    /// Requires the `tokio` feature, and a runtime with the IO and time drivers enabled.
+    #[cfg(feature = "tokio")]
+    pub async fn exec_async(&self) -> Result<Metadata> {
+        let mut command = tokio::process::Command::from(self.cargo_command());
+        if self.verbose {
+            command.stderr(Stdio::inherit());
+        } else {
+            command.stderr(Stdio::piped());
+        }
+        command.stdin(Stdio::null()).kill_on_drop(true);
+        let output = match self.timeout {
+            Some(timeout) => {
+                let start = Instant::now();
+                tokio::time::timeout(timeout, command.output())
+                    .await
+                    .map_err(|_| Error::Timeout {
+                        elapsed: start.elapsed(),
+                    })??
+            }
+            None => command.output().await?,
+        };
+        self.parse_output(&output).map(|(meta, _)| meta)
+    }
+
+    /// Parses the output of `cargo metadata`, the shared tail of `exec`, `exec_raw` and
+    /// `exec_async`. Also returns the json, borrowed from `output`.
+    fn parse_output<'a>(&self, output: &'a Output) -> Result<(Metadata, &'a str)> {
         if !output.status.success() {
             return Err(Error::CargoMetadata {
-                stderr: String::from_utf8(output.stderr)?,
+                stderr: String::from_utf8(output.stderr.clone())?,
             });
         }
         let stdout = from_utf8(&output.stdout)?
             .lines()
             .find(|line| line.starts_with('{'))
             .ok_or(Error::NoJson)?;
-        Self::parse(stdout)
+        let mut meta = if self.strict {
+            Self::parse_strict(stdout)?
+        } else {
+            Self::parse(stdout)?
+        };
+        if let Some(name) = &self.package {
+            meta.packages.retain(|pkg| pkg.name == *name);
+            if meta.packages.is_empty() {
+                return Err(Error::PackageNotFound { name: name.clone() });
+            }
+        }
+        Ok((meta, stdout))
+    }
+}
+
+/// A builder for configuring a `cargo build --message-format=json` invocation.
+///
+/// ```no_run
+/// # use cargo_metadata::{BuildCommand, Message};
+/// for message in BuildCommand::new().lib().bin("foo").exec().unwrap() {
+///     if let Message::CompilerArtifact(artifact) = message.unwrap() {
+///         println!("{:?}", artifact.filenames);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BuildCommand {
+    /// Path to `cargo` executable.  If not set, this will use the
+    /// the `$CARGO` environment variable, and if that is not set, will
+    /// simply be `cargo`.
+    cargo_path: Option<PathBuf>,
+    /// Path to `Cargo.toml`
+    manifest_path: Option<PathBuf>,
+    /// Current directory of the `cargo build` process.
+    current_dir: Option<PathBuf>,
+    /// Feature flags to pass to `cargo`
+    features: FeatureFlags,
+    /// Target selection flags, e.g. `--lib` or `--bin foo`
+    targets: Vec<String>,
+    /// Target triple to build for
+    target: Option<String>,
+    /// Arbitrary command line flags to pass to `cargo`.  These will be added
+    /// to the end of the command line invocation.
+    other_options: Vec<String>,
+    /// Arbitrary environment variables to set when running `cargo`.
+    env: BTreeMap<OsString, OsString>,
+    /// Show stderr
+    verbose: bool,
+}
+
+impl BuildCommand {
+    /// Creates a default `cargo build` command, which builds the default targets of the
+    /// package in the current directory.
+    pub fn new() -> BuildCommand {
+        BuildCommand::default()
+    }
+    /// Path to `cargo` executable.  If not set, this will use the
+    /// the `$CARGO` environment variable, and if that is not set, will
+    /// simply be `cargo`.
+    pub fn cargo_path(&mut self, path: impl Into<PathBuf>) -> &mut BuildCommand {
+        self.cargo_path = Some(path.into());
+        self
+    }
+    /// Path to `Cargo.toml`
+    pub fn manifest_path(&mut self, path: impl Into<PathBuf>) -> &mut BuildCommand {
+        self.manifest_path = Some(path.into());
+        self
+    }
+    /// Current directory of the `cargo build` process.
+    pub fn current_dir(&mut self, path: impl Into<PathBuf>) -> &mut BuildCommand {
+        self.current_dir = Some(path.into());
+        self
+    }
+    /// Which features to include, see [`MetadataCommand::features`].
+    ///
+    /// # Panics
+    ///
+    /// Panics when specifying `CargoOpt::NoDefaultFeatures` or `CargoOpt::AllFeatures`
+    /// more than once.
+    pub fn features(&mut self, features: CargoOpt) -> &mut BuildCommand {
+        self.features.add(features);
+        self
+    }
+    /// Build the library target (`--lib`).
+    pub fn lib(&mut self) -> &mut BuildCommand {
+        self.targets.push("--lib".into());
+        self
+    }
+    /// Build all binary targets (`--bins`).
+    pub fn bins(&mut self) -> &mut BuildCommand {
+        self.targets.push("--bins".into());
+        self
+    }
+    /// Build the binary target with the given name (`--bin <name>`).
+    pub fn bin(&mut self, name: impl Into<String>) -> &mut BuildCommand {
+        self.targets.push("--bin".into());
+        self.targets.push(name.into());
+        self
+    }
+    /// Build all example targets (`--examples`).
+    pub fn examples(&mut self) -> &mut BuildCommand {
+        self.targets.push("--examples".into());
+        self
+    }
+    /// Build all test targets (`--tests`).
+    pub fn tests(&mut self) -> &mut BuildCommand {
+        self.targets.push("--tests".into());
+        self
+    }
+    /// Build all targets of the selected packages (`--all-targets`).
+    pub fn all_targets(&mut self) -> &mut BuildCommand {
+        self.targets.push("--all-targets".into());
+        self
+    }
+    /// Build for the given target triple (`--target`) instead of the host, e.g.
+    /// `wasm32-unknown-unknown`.
+    pub fn target(&mut self, triple: impl Into<String>) -> &mut BuildCommand {
+        self.target = Some(triple.into());
+        self
+    }
+    /// Arbitrary command line flags to pass to `cargo`.  These will be added
+    /// to the end of the command line invocation.
+    pub fn other_options(&mut self, options: impl Into<Vec<String>>) -> &mut BuildCommand {
+        self.other_options = options.into();
+        self
+    }
+    /// Arbitrary environment variables to set when running `cargo`.  These will be merged into
+    /// the calling environment, overriding any which clash.
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(
+        &mut self,
+        key: K,
+        val: V,
+    ) -> &mut BuildCommand {
+        self.env.insert(key.into(), val.into());
+        self
+    }
+    /// Set whether to show stderr
+    pub fn verbose(&mut self, verbose: bool) -> &mut BuildCommand {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Builds a command for `cargo build`.  This is the first
+    /// part of the work of `exec`.
+    pub fn cargo_command(&self) -> Command {
+        let mut cmd = new_cargo_command(self.cargo_path.as_ref(), None);
+        cmd.args(["build", "--message-format=json"]);
+
+        if let Some(path) = self.current_dir.as_ref() {
+            cmd.current_dir(path);
+        }
+
+        self.features.apply(&mut cmd);
+        cmd.args(&self.targets);
+        if let Some(target) = &self.target {
+            cmd.arg("--target").arg(target);
+        }
+
+        if let Some(manifest_path) = &self.manifest_path {
+            cmd.arg("--manifest-path").arg(manifest_path.as_os_str());
+        }
+        cmd.args(&self.other_options);
+
+        cmd.envs(&self.env);
+
+        cmd
+    }
+
+    /// Runs configured `cargo build` and returns the messages it emitted.
+    ///
+    /// A build that fails to compile still returns its messages, including the
+    /// compiler errors and a [`BuildFinished`] message reporting the failure.
+    /// [`Error::CargoBuild`] is only returned if `cargo` exited with an error without
+    /// emitting any messages, e.g. because the manifest couldn't be read.
+    pub fn exec(&self) -> Result<MessageIter<io::Cursor<Vec<u8>>>> {
+        let mut command = self.cargo_command();
+        if self.verbose {
+            command.stderr(Stdio::inherit());
+        }
+        let output = command.output()?;
+        if !output.status.success() && output.stdout.is_empty() {
+            return Err(Error::CargoBuild {
+                stderr: String::from_utf8(output.stderr)?,
+            });
+        }
+        Ok(Message::parse_stream(io::Cursor::new(output.stdout)))
     }
 }
 
+/// Like [`Command::output`], but kills the process if it runs for longer than `timeout`.
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> Result<Output> {
+    let start = Instant::now();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    // Drain the pipes on their own threads, so that the process can't block on a full pipe
+    // while we are waiting for it to exit.
+    let stdout = read_to_end_in_thread(child.stdout.take());
+    let stderr = read_to_end_in_thread(child.stderr.take());
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Output {
+                status,
+                stdout: stdout.join().expect("failed to read stdout")?,
+                stderr: stderr.join().expect("failed to read stderr")?,
+            });
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            // Ignore errors, the process may have exited in the meantime.
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Timeout { elapsed });
+        }
+        thread::sleep((timeout - elapsed).min(Duration::from_millis(10)));
+    }
+}
+
+fn read_to_end_in_thread<R: io::Read + Send + 'static>(
+    pipe: Option<R>,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+/// The `CargoOpt`s passed to a command.
+#[derive(Debug, Clone, Default)]
+struct FeatureFlags {
+    /// Collections of `CargoOpt::SomeFeatures(..)`
+    features: Vec<String>,
+    /// Latched `CargoOpt::AllFeatures`
+    all_features: bool,
+    /// Latched `CargoOpt::NoDefaultFeatures`
+    no_default_features: bool,
+}
+
+impl FeatureFlags {
+    fn add(&mut self, features: CargoOpt) {
+        match features {
+            CargoOpt::SomeFeatures(features) => self.features.extend(features),
+            CargoOpt::NoDefaultFeatures => {
+                assert!(
+                    !self.no_default_features,
+                    "Do not supply CargoOpt::NoDefaultFeatures more than once!"
+                );
+                self.no_default_features = true;
+            }
+            CargoOpt::AllFeatures => {
+                assert!(
+                    !self.all_features,
+                    "Do not supply CargoOpt::AllFeatures more than once!"
+                );
+                self.all_features = true;
+            }
+        }
+    }
+
+    fn apply(&self, cmd: &mut Command) {
+        if !self.features.is_empty() {
+            cmd.arg("--features").arg(self.features.join(","));
+        }
+        if self.all_features {
+            cmd.arg("--all-features");
+        }
+        if self.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+    }
+}
+
+/// Creates a `cargo` command, preferring an explicit path, then `$CARGO` unless a rustup
+/// toolchain is requested, and finally the `cargo` in `$PATH`.
+fn new_cargo_command(cargo_path: Option<&PathBuf>, toolchain: Option<&str>) -> Command {
+    let cargo = cargo_path
+        .cloned()
+        .or_else(|| match toolchain {
+            Some(_) => None,
+            None => env::var("CARGO").map(PathBuf::from).ok(),
+        })
+        .unwrap_or_else(|| PathBuf::from("cargo"));
+    let mut cmd = Command::new(cargo);
+    if let Some(toolchain) = toolchain {
+        cmd.arg(format!("+{}", toolchain));
+    }
+    cmd
+}
+
 /// As per the Cargo Book the [`rust-version` field](https://doc.rust-lang.org/cargo/reference/manifest.html#the-rust-version-field) must:
 ///
 /// > be a bare version number with two or three components;
@@ -1112,7 +2527,8 @@ impl MetadataCommand {
 ///
 /// [`semver::Version`] however requires three components. This function takes
 /// care of appending `.0` if the provided version number only has two components
-/// and ensuring that it does not contain a pre-release version or build metadata.
+/// (ignoring a trailing `.`, as in `1.2.`) and ensuring that it does not contain a
+/// pre-release version, build metadata or more than three components.
 fn deserialize_rust_version<'de, D>(
     deserializer: D,
 ) -> std::result::Result<Option<Version>, D::Error>
@@ -1136,9 +2552,20 @@ where
         }
     }
 
-    if buf.matches('.').count() == 1 {
+    if buf.ends_with('.') && buf.matches('.').count() == 2 {
+        // e.g. 1.0. -> 1.0
+        buf.pop();
+    }
+
+    match buf.matches('.').count() {
         // e.g. 1.0 -> 1.0.0
-        buf.push_str(".0");
+        1 => buf.push_str(".0"),
+        n if n > 2 => {
+            return Err(serde::de::Error::custom(
+                "rust-version must have at most three components",
+            ))
+        }
+        _ => {}
     }
 
     Ok(Some(
@@ -1180,5 +2607,11 @@ mod test {
             bare_version_err("1.2.0+123"),
             "build metadata is not supported in rust-version"
         );
+        assert_eq!(bare_version("1.2."), Version::new(1, 2, 0));
+        assert_eq!(bare_version("1.2.3"), Version::new(1, 2, 3));
+        assert_eq!(
+            bare_version_err("1.2.3.4"),
+            "rust-version must have at most three components"
+        );
     }
 }
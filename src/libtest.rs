@@ -2,6 +2,7 @@
 //!
 //! Since this module parses output in an unstable format, all structs in this module may change at any time, and are exempt from semver guarantees.
 use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Read};
 
 /// Suite related event
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
@@ -60,8 +61,8 @@ pub enum TestEvent {
     Ok {
         /// which one
         name: String,
-        /// in how long
-        exec_time: f32,
+        /// in how long, only reported with `--report-time`
+        exec_time: Option<f32>,
         /// what did it say?
         stdout: Option<String>,
     },
@@ -69,8 +70,8 @@ pub enum TestEvent {
     Failed {
         /// which one
         name: String,
-        /// in how long
-        exec_time: f32,
+        /// in how long, only reported with `--report-time`
+        exec_time: Option<f32>,
         /// why?
         stdout: Option<String>,
         /// it timed out?
@@ -113,7 +114,8 @@ impl TestEvent {
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 /// Represents the output of `cargo test -- -Zunstable-options --report-time --show-output --format json`.
 ///
-/// requires --report-time
+/// `--report-time` and `--show-output` are optional. Without them the `exec_time` and `stdout`
+/// of passing tests are `None`.
 ///
 /// # Stability
 ///
@@ -138,6 +140,125 @@ pub enum TestMessage {
     },
 }
 
+/// The phase of a `cargo test` run a test belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// unit and integration tests, run from the compiled test binaries
+    Unit,
+    /// doctests, run by rustdoc after the test binaries of a package
+    Doc,
+}
+
+/// Parses a `cargo test` json stream, tagging each [`TestEvent`] with the [`Phase`] it ran in.
+///
+/// Every test binary and every doctest run starts its own suite. A suite is assumed to be a
+/// unit test suite until one of its tests carries a doctest name (`src/lib.rs - foo (line 3)`),
+/// at which point the rest of the suite is attributed to [`Phase::Doc`].
+///
+/// Suite and bench messages are only used to track the phase and are not yielded.
+/// Lines which aren't libtest json messages are skipped.
+pub struct TestEventParser<R> {
+    input: R,
+    phase: Phase,
+}
+
+impl<R> TestEventParser<R> {
+    /// Creates an iterator of `(Phase, TestEvent)` from a Read outputting a stream of libtest
+    /// json messages.
+    pub fn parse(input: R) -> Self {
+        TestEventParser {
+            input,
+            phase: Phase::Unit,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TestEventParser<R> {
+    type Item = io::Result<(Phase, TestEvent)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.input.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            match serde_json::from_str::<TestMessage>(&line) {
+                Ok(TestMessage::Suite(SuiteEvent::Started { .. })) => self.phase = Phase::Unit,
+                Ok(TestMessage::Test(event)) => {
+                    if is_doctest_name(event.name()) {
+                        self.phase = Phase::Doc;
+                    }
+                    return Some(Ok((self.phase, event)));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Creates an iterator of the [`TestEvent`]s in a libtest json stream, e.g. the output of
+/// `cargo test -- -Zunstable-options --format json`.
+///
+/// Lines which aren't test events are skipped, see [`TestEventParser`].
+pub fn parse_test_stream<R: Read>(
+    input: R,
+) -> impl Iterator<Item = Result<TestEvent, crate::Error>> {
+    TestEventParser::parse(io::BufReader::new(input))
+        .map(|event| event.map(|(_, event)| event).map_err(crate::Error::from))
+}
+
+/// The results of the tests in a libtest json stream.
+///
+/// Build it by collecting [`TestEvent`]s, e.g. from [`parse_test_stream`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestSuiteSummary {
+    /// number of tests that finished or were ignored
+    pub total: usize,
+    /// number of tests that passed
+    pub passed: usize,
+    /// number of tests that failed
+    pub failed: usize,
+    /// number of tests that were ignored
+    pub ignored: usize,
+    /// the names of the failed tests, with their stdout if it was captured
+    pub failures: Vec<(String, Option<String>)>,
+}
+
+impl TestSuiteSummary {
+    /// Adds the result of a single test event to the summary.
+    ///
+    /// `Started` and `Timeout` events don't carry a result and are ignored.
+    pub fn add(&mut self, event: &TestEvent) {
+        match event {
+            TestEvent::Ok { .. } => self.passed += 1,
+            TestEvent::Failed { name, stdout, .. } => {
+                self.failed += 1;
+                self.failures.push((name.clone(), stdout.clone()));
+            }
+            TestEvent::Ignored { .. } => self.ignored += 1,
+            TestEvent::Started { .. } | TestEvent::Timeout { .. } => return,
+        }
+        self.total += 1;
+    }
+}
+
+impl FromIterator<TestEvent> for TestSuiteSummary {
+    fn from_iter<I: IntoIterator<Item = TestEvent>>(events: I) -> Self {
+        let mut summary = TestSuiteSummary::default();
+        for event in events {
+            summary.add(&event);
+        }
+        summary
+    }
+}
+
+/// rustdoc names doctests after their location, e.g. `src/lib.rs - foo::bar (line 3)`.
+fn is_doctest_name(name: &str) -> bool {
+    name.contains(" - ") && name.contains(" (line ") && name.ends_with(')')
+}
+
 #[test]
 fn deser() {
     macro_rules! run {
@@ -148,7 +269,7 @@ fn deser() {
     run![
         r#"{ "type": "suite", "event": "started", "test_count": 2 }"# parses to TestMessage::Suite(SuiteEvent::Started { test_count: 2 }),
         r#"{ "type": "test", "event": "started", "name": "fail" }"# parses to TestMessage::Test(TestEvent::Started { name: "fail".into() }),
-        r#"{ "type": "test", "name": "fail", "event": "ok", "exec_time": 0.000003428, "stdout": "hello world" }"# parses to TestMessage::Test(TestEvent::Ok { name: "fail".into(), exec_time: 0.000003428, stdout: Some("hello world".into()) }),
+        r#"{ "type": "test", "name": "fail", "event": "ok", "exec_time": 0.000003428, "stdout": "hello world" }"# parses to TestMessage::Test(TestEvent::Ok { name: "fail".into(), exec_time: Some(0.000003428), stdout: Some("hello world".into()) }),
         r#"{ "type": "test", "event": "started", "name": "nope" }"# parses to TestMessage::Test(TestEvent::Started { name: "nope".into() }),
         r#"{ "type": "test", "name": "nope", "event": "ignored" }"# parses to TestMessage::Test(TestEvent::Ignored { name: "nope".into() }),
         r#"{ "type": "suite", "event": "ok", "passed": 1, "failed": 0, "ignored": 1, "measured": 0, "filtered_out": 0, "exec_time": 0.000684028 }"# parses to TestMessage::Suite(SuiteEvent::Ok { passed: 1, failed: 0, ignored: 1, measured: 0, filtered_out: 0, exec_time: 0.000684028 })
@@ -159,7 +280,118 @@ fn deser() {
         r#"{ "type": "test", "event": "started", "name": "fail" }"# parses to TestMessage::Test(TestEvent::Started { name: "fail".into() }),
         r#"{ "type": "test", "event": "started", "name": "benc" }"# parses to TestMessage::Test(TestEvent::Started { name: "benc".into() }),
         r#"{ "type": "bench", "name": "benc", "median": 0, "deviation": 0 }"# parses to TestMessage::Bench { name: "benc".into(), median: 0., deviation: 0., mib_per_second: None },
-        r#"{ "type": "test", "name": "fail", "event": "failed", "exec_time": 0.000081092, "stdout": "thread 'fail' panicked" }"# parses to TestMessage::Test(TestEvent::Failed { name: "fail".into(), exec_time: 0.000081092, stdout: Some("thread 'fail' panicked".into()), reason: None, message: None} ),
+        r#"{ "type": "test", "name": "fail", "event": "failed", "exec_time": 0.000081092, "stdout": "thread 'fail' panicked" }"# parses to TestMessage::Test(TestEvent::Failed { name: "fail".into(), exec_time: Some(0.000081092), stdout: Some("thread 'fail' panicked".into()), reason: None, message: None} ),
         r#"{ "type": "suite", "event": "failed", "passed": 0, "failed": 1, "ignored": 0, "measured": 1, "filtered_out": 0, "exec_time": 0.000731068 }"# parses to TestMessage::Suite(SuiteEvent::Failed { passed: 0, failed: 1, ignored: 0, measured: 1, filtered_out: 0, exec_time: 0.000731068 })
     ];
 }
+
+#[test]
+fn without_report_time() {
+    // Recorded from `cargo +nightly test -- -Zunstable-options --format json`.
+    let input = r#"{ "type": "test", "name": "t", "event": "ok" }"#;
+    assert_eq!(
+        serde_json::from_str::<TestMessage>(input).unwrap(),
+        TestMessage::Test(TestEvent::Ok {
+            name: "t".into(),
+            exec_time: None,
+            stdout: None,
+        })
+    );
+}
+
+#[test]
+fn bench() {
+    // Recorded from `cargo +nightly bench -- -Zunstable-options --format json`.
+    let input = r#"{ "type": "bench", "name": "benc", "median": 0.4180710275245892, "deviation": 0.483371476721737 }"#;
+    assert_eq!(
+        serde_json::from_str::<TestMessage>(input).unwrap(),
+        TestMessage::Bench {
+            name: "benc".into(),
+            median: 0.418_071_03,
+            deviation: 0.483_371_48,
+            mib_per_second: None,
+        }
+    );
+}
+
+#[test]
+fn round_trip() {
+    let input = r#"{ "type": "test", "name": "fail", "event": "failed", "exec_time": 0.000081092, "stdout": "thread 'fail' panicked", "message": "assertion failed" }"#;
+    let message = serde_json::from_str::<TestMessage>(input).unwrap();
+    let serialized = serde_json::to_string(&message).unwrap();
+    assert_eq!(
+        serde_json::from_str::<TestMessage>(&serialized).unwrap(),
+        message
+    );
+
+    let event = TestEvent::Ok {
+        name: "ok".into(),
+        exec_time: Some(0.5),
+        stdout: None,
+    };
+    let serialized = serde_json::to_string(&event).unwrap();
+    assert_eq!(
+        serde_json::from_str::<TestEvent>(&serialized).unwrap(),
+        event
+    );
+}
+
+#[test]
+fn parse_phases() {
+    let input = r#"{ "type": "suite", "event": "started", "test_count": 1 }
+{ "type": "test", "event": "started", "name": "tests::unit" }
+{ "type": "test", "name": "tests::unit", "event": "ok", "exec_time": 0.000003428 }
+{ "type": "suite", "event": "ok", "passed": 1, "failed": 0, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": 0.000684028 }
+   Doc-tests foo
+{ "type": "suite", "event": "started", "test_count": 1 }
+{ "type": "test", "event": "started", "name": "src/lib.rs - foo (line 3)" }
+{ "type": "test", "name": "src/lib.rs - foo (line 3)", "event": "failed", "exec_time": 0.000081092, "stdout": "oops" }
+{ "type": "suite", "event": "failed", "passed": 0, "failed": 1, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": 0.000731068 }
+{ "type": "suite", "event": "started", "test_count": 1 }
+{ "type": "test", "event": "started", "name": "other_crate_test" }
+"#;
+    let events = TestEventParser::parse(input.as_bytes())
+        .map(|event| event.map(|(phase, event)| (phase, event.name().to_owned())))
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        events,
+        vec![
+            (Phase::Unit, "tests::unit".to_owned()),
+            (Phase::Unit, "tests::unit".to_owned()),
+            (Phase::Doc, "src/lib.rs - foo (line 3)".to_owned()),
+            (Phase::Doc, "src/lib.rs - foo (line 3)".to_owned()),
+            (Phase::Unit, "other_crate_test".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn summary() {
+    let input = r#"{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "fail" }
+{ "type": "test", "name": "fail", "event": "ok", "exec_time": 0.000003428, "stdout": "hello world" }
+{ "type": "test", "event": "started", "name": "nope" }
+{ "type": "test", "name": "nope", "event": "ignored" }
+{ "type": "suite", "event": "ok", "passed": 1, "failed": 0, "ignored": 1, "measured": 0, "filtered_out": 0, "exec_time": 0.000684028 }
+{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "fail" }
+{ "type": "test", "event": "started", "name": "benc" }
+{ "type": "bench", "name": "benc", "median": 0, "deviation": 0 }
+{ "type": "test", "name": "fail", "event": "failed", "exec_time": 0.000081092, "stdout": "thread 'fail' panicked" }
+{ "type": "suite", "event": "failed", "passed": 0, "failed": 1, "ignored": 0, "measured": 1, "filtered_out": 0, "exec_time": 0.000731068 }
+"#;
+    let summary = parse_test_stream(input.as_bytes())
+        .collect::<Result<TestSuiteSummary, _>>()
+        .unwrap();
+    assert_eq!(
+        summary,
+        TestSuiteSummary {
+            total: 3,
+            passed: 1,
+            failed: 1,
+            ignored: 1,
+            failures: vec![("fail".into(), Some("thread 'fail' panicked".into()))],
+        }
+    );
+}
@@ -1,10 +1,15 @@
 use super::{Diagnostic, PackageId, Target};
+use crate::diagnostic::DiagnosticLevel;
 use camino::Utf8PathBuf;
+use cargo_platform::Cfg;
 #[cfg(feature = "builder")]
 use derive_builder::Builder;
 use serde::{de, ser, Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::{self, Write};
 use std::io::{self, BufRead, Read};
+use std::iter::FusedIterator;
+use std::str::FromStr;
 
 /// Profile settings used to determine which compiler flags to use for a
 /// target.
@@ -13,8 +18,8 @@ use std::io::{self, BufRead, Read};
 #[non_exhaustive]
 #[cfg_attr(feature = "builder", builder(pattern = "owned", setter(into)))]
 pub struct ArtifactProfile {
-    /// Optimization level. Possible values are 0-3, s or z.
-    pub opt_level: String,
+    /// Optimization level.
+    pub opt_level: OptLevel,
     /// The kind of debug information.
     #[serde(default)]
     pub debuginfo: ArtifactDebuginfo,
@@ -27,6 +32,69 @@ pub struct ArtifactProfile {
     pub test: bool,
 }
 
+/// The optimization level of an artifact.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OptLevel {
+    /// No optimizations (`0`).
+    #[serde(rename = "0")]
+    Zero,
+    /// Basic optimizations (`1`).
+    #[serde(rename = "1")]
+    One,
+    /// Some optimizations (`2`).
+    #[serde(rename = "2")]
+    Two,
+    /// All optimizations (`3`).
+    #[serde(rename = "3")]
+    Three,
+    /// Optimize for binary size (`s`).
+    #[serde(rename = "s")]
+    S,
+    /// Optimize for binary size, but also turn off loop vectorization (`z`).
+    #[serde(rename = "z")]
+    Z,
+    /// Unknown level
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl From<&str> for OptLevel {
+    fn from(value: &str) -> Self {
+        match value {
+            "0" => OptLevel::Zero,
+            "1" => OptLevel::One,
+            "2" => OptLevel::Two,
+            "3" => OptLevel::Three,
+            "s" => OptLevel::S,
+            "z" => OptLevel::Z,
+            x => OptLevel::Unknown(x.to_string()),
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(OptLevel::from(s))
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero => "0".fmt(f),
+            Self::One => "1".fmt(f),
+            Self::Two => "2".fmt(f),
+            Self::Three => "3".fmt(f),
+            Self::S => "s".fmt(f),
+            Self::Z => "z".fmt(f),
+            Self::Unknown(x) => x.fmt(f),
+        }
+    }
+}
+
 /// The kind of debug information included in the artifact.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 #[non_exhaustive]
@@ -212,6 +280,68 @@ pub struct BuildScript {
     pub out_dir: Utf8PathBuf,
 }
 
+impl BuildScript {
+    /// The value of the environment variable `key` set by the build script.
+    ///
+    /// If the variable was set multiple times, the last value wins, as in the compilation.
+    pub fn env_var(&self, key: &str) -> Option<&str> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Splits the optional `KIND=` prefix off [`linked_paths`](BuildScript::linked_paths),
+    /// e.g. `native=/usr/lib` into `(Some("native"), "/usr/lib")`.
+    ///
+    /// Like rustc, only the kinds `dependency`, `crate`, `native`, `framework` and `all`
+    /// are recognized, so that paths containing a `=` are kept intact.
+    pub fn parsed_linked_paths(&self) -> Vec<(Option<String>, Utf8PathBuf)> {
+        self.linked_paths
+            .iter()
+            .map(|path| match path.as_str().split_once('=') {
+                Some((kind @ ("dependency" | "crate" | "native" | "framework" | "all"), path)) => {
+                    (Some(kind.to_owned()), Utf8PathBuf::from(path))
+                }
+                _ => (None, path.clone()),
+            })
+            .collect()
+    }
+
+    /// Splits the optional `KIND=` prefix off [`linked_libs`](BuildScript::linked_libs),
+    /// e.g. `static=foo` into `(Some("static"), "foo")`.
+    ///
+    /// The kind includes any linking modifiers, like `static:+whole-archive`.
+    pub fn parsed_linked_libs(&self) -> Vec<(Option<String>, String)> {
+        self.linked_libs
+            .iter()
+            .map(|lib| match lib.as_str().split_once('=') {
+                Some((kind, name)) => (Some(kind.to_owned()), name.to_owned()),
+                None => (None, lib.to_string()),
+            })
+            .collect()
+    }
+
+    /// Parses [`cfgs`](BuildScript::cfgs) into structured cfg values, like `unix` or
+    /// `feature = "serde"`.
+    ///
+    /// Fails if a cfg doesn't follow rustc's cfg syntax.
+    pub fn parsed_cfgs(&self) -> Result<Vec<Cfg>, cargo_platform::ParseError> {
+        self.cfgs.iter().map(|cfg| cfg.parse()).collect()
+    }
+
+    /// The environment variables set by the build script, mapped to their values.
+    ///
+    /// If a variable was set multiple times, the last value wins, as in the compilation.
+    pub fn env_map(&self) -> BTreeMap<&str, &str> {
+        self.env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+}
+
 /// Final result of a build.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "builder", derive(Builder))]
@@ -240,6 +370,9 @@ pub enum Message {
     BuildFinished(BuildFinished),
     /// A line of text which isn't a cargo or compiler message.
     /// Line separator is not included
+    ///
+    /// This is only produced by [`Message::parse_stream`] and can't be serialized, since
+    /// it isn't JSON.
     #[serde(skip)]
     TextLine(String),
 }
@@ -247,8 +380,45 @@ pub enum Message {
 impl Message {
     /// Creates an iterator of Message from a Read outputting a stream of JSON
     /// messages. For usage information, look at the top-level documentation.
+    ///
+    /// This is the preferred way to read messages: errors from the reader are
+    /// reported as `io::Error`s, which convert into the crate's [`Error::Io`],
+    /// while lines that aren't JSON messages are yielded as `Message::TextLine`.
+    ///
+    /// [`Error::Io`]: crate::Error::Io
     pub fn parse_stream<R: Read>(input: R) -> MessageIter<R> {
-        MessageIter { input }
+        MessageIter { input, done: false }
+    }
+
+    /// Like [`Message::parse_stream`], but yields the crate's [`Error`](crate::Error)
+    /// instead of `io::Error`, e.g. to use `?` in functions returning
+    /// [`Result`](crate::Result).
+    ///
+    /// Errors from the reader are reported as [`Error::Io`](crate::Error::Io). Lines that
+    /// aren't JSON messages are still yielded as `Message::TextLine` rather than errors.
+    pub fn parse_stream_results<R: Read>(input: R) -> MessageResults<R> {
+        MessageResults {
+            inner: Message::parse_stream(input),
+        }
+    }
+
+    /// The package this message was emitted for, if any.
+    ///
+    /// `BuildFinished` and `TextLine` messages don't belong to a package.
+    pub fn package_id(&self) -> Option<&PackageId> {
+        match self {
+            Message::CompilerArtifact(artifact) => Some(&artifact.package_id),
+            Message::CompilerMessage(msg) => Some(&msg.package_id),
+            Message::BuildScriptExecuted(script) => Some(&script.package_id),
+            Message::BuildFinished(_) | Message::TextLine(_) => None,
+        }
+    }
+}
+
+impl CompilerMessage {
+    /// The level of the diagnostic, e.g. `warning` or `error`.
+    pub fn level(&self) -> DiagnosticLevel {
+        self.message.level
     }
 }
 
@@ -259,15 +429,25 @@ impl fmt::Display for CompilerMessage {
 }
 
 /// An iterator of Messages.
+///
+/// The iterator is fused: once the reader is exhausted, or after it has
+/// yielded an I/O error from the reader, it keeps returning `None`. A line
+/// that isn't valid UTF-8 is the exception: it is reported as an
+/// `InvalidData` error and the following lines are still read.
 pub struct MessageIter<R> {
     input: R,
+    done: bool,
 }
 
 impl<R: BufRead> Iterator for MessageIter<R> {
     type Item = io::Result<Message>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
         let mut line = String::new();
-        self.input
+        let message = self
+            .input
             .read_line(&mut line)
             .map(|n| {
                 if n == 0 {
@@ -281,8 +461,106 @@ impl<R: BufRead> Iterator for MessageIter<R> {
                     Some(Message::deserialize(&mut deserializer).unwrap_or(Message::TextLine(line)))
                 }
             })
-            .transpose()
+            .transpose();
+        self.done = match &message {
+            None => true,
+            Some(Ok(_)) => false,
+            // `read_line` has consumed the offending line, so the rest of the
+            // stream can still be read.
+            Some(Err(e)) => e.kind() != io::ErrorKind::InvalidData,
+        };
+        message
+    }
+}
+
+impl<R: BufRead> FusedIterator for MessageIter<R> {}
+
+/// An iterator of Messages yielding the crate's [`Error`](crate::Error), see
+/// [`Message::parse_stream_results`].
+pub struct MessageResults<R> {
+    inner: MessageIter<R>,
+}
+
+impl<R: BufRead> Iterator for MessageResults<R> {
+    type Item = crate::Result<Message>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|message| message.map_err(crate::Error::Io))
+    }
+}
+
+impl<R: BufRead> FusedIterator for MessageResults<R> {}
+
+/// Creates an iterator of the test executables built by
+/// `cargo test --no-run --message-format=json`, along with the package they belong to.
+pub fn test_executables<R: Read>(
+    input: R,
+) -> impl Iterator<Item = crate::Result<(PackageId, Utf8PathBuf)>> {
+    Message::parse_stream(io::BufReader::new(input)).filter_map(|message| match message {
+        Ok(Message::CompilerArtifact(Artifact {
+            package_id,
+            profile,
+            executable: Some(executable),
+            ..
+        })) if profile.test => Some(Ok((package_id, executable))),
+        Ok(_) => None,
+        Err(err) => Some(Err(err.into())),
+    })
+}
+
+/// Drains a stream of JSON messages, e.g. from `cargo build --message-format=json`, and
+/// returns the executables of all `bin` targets that were built.
+pub fn collect_executables<R: Read>(input: R) -> crate::Result<Vec<Utf8PathBuf>> {
+    let mut executables = Vec::new();
+    for message in Message::parse_stream(io::BufReader::new(input)) {
+        if let Message::CompilerArtifact(Artifact {
+            target,
+            executable: Some(executable),
+            ..
+        }) = message?
+        {
+            if target.is_bin() {
+                executables.push(executable);
+            }
+        }
+    }
+    Ok(executables)
+}
+
+/// Drains a stream of JSON messages and returns the compiler messages that are at
+/// least as severe as `min_level`, see [`DiagnosticLevel::is_at_least`].
+pub fn collect_diagnostics<R: Read>(
+    input: R,
+    min_level: DiagnosticLevel,
+) -> crate::Result<Vec<CompilerMessage>> {
+    let mut diagnostics = Vec::new();
+    for message in Message::parse_stream(io::BufReader::new(input)) {
+        if let Message::CompilerMessage(msg) = message? {
+            if msg.level().is_at_least(min_level) {
+                diagnostics.push(msg);
+            }
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Drains a stream of JSON messages and buckets them by the package they
+/// were emitted for, keeping the stream order within each package.
+///
+/// Messages without a package, like `BuildFinished`, are stored under `None`.
+pub fn group_by_package<R: Read>(
+    input: R,
+) -> crate::Result<BTreeMap<Option<PackageId>, Vec<Message>>> {
+    let mut groups: BTreeMap<Option<PackageId>, Vec<Message>> = BTreeMap::new();
+    for message in Message::parse_stream(io::BufReader::new(input)) {
+        let message = message?;
+        groups
+            .entry(message.package_id().cloned())
+            .or_default()
+            .push(message);
     }
+    Ok(groups)
 }
 
 /// An iterator of Message.
@@ -1,9 +1,14 @@
+use std::collections::BTreeMap;
 use std::env::current_dir;
 use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
 
 use semver::Version;
 
-use cargo_metadata::{CargoOpt, Error, MetadataCommand};
+use cargo_metadata::{
+    BuildCommand, CargoOpt, DependencyKind, Error, Message, MetadataCommand, Source,
+};
 use serde::Deserialize;
 
 #[derive(Debug, PartialEq, Eq, Deserialize)]
@@ -19,6 +24,7 @@ fn metadata() {
     let this = &metadata.packages[0];
     assert_eq!(this.name, "cargo_metadata");
     assert_eq!(this.targets.len(), 3);
+    assert_eq!(metadata.resolver_version(), Some(2));
 
     let lib = this
         .targets
@@ -27,11 +33,15 @@ fn metadata() {
         .unwrap();
     assert_eq!(lib.kind[0], "lib".into());
     assert_eq!(lib.crate_types[0], "lib".into());
+    assert!(lib.doc);
+    assert!(lib.test);
+    assert!(lib.runs_doctests());
 
     let selftest = this.targets.iter().find(|t| t.name == "selftest").unwrap();
     assert_eq!(selftest.name, "selftest");
     assert_eq!(selftest.kind[0], "test".into());
     assert_eq!(selftest.crate_types[0], "bin".into());
+    assert!(!selftest.runs_doctests());
 
     let package_metadata = &metadata.packages[0]
         .metadata
@@ -48,6 +58,19 @@ fn metadata() {
             other_field: "foo".into(),
         }
     );
+
+    let this = &metadata.packages[0];
+    assert_eq!(this.metadata_table("cargo_metadata_test"), Some(value));
+    assert_eq!(this.metadata_table("nope"), None);
+    let typed = this
+        .metadata_typed::<BTreeMap<String, TestPackageMetadata>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(typed["cargo_metadata_test"], test_package_metadata);
+
+    let mut this = this.clone();
+    this.metadata = serde_json::Value::Null;
+    assert_eq!(this.metadata_typed::<TestPackageMetadata>().unwrap(), None);
 }
 
 #[test]
@@ -81,13 +104,130 @@ fn builder_interface() {
         .unwrap();
 }
 
+#[test]
+fn cargo_command_args() {
+    let cmd = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .features(CargoOpt::SomeFeatures(vec!["feat1".into(), "feat2".into()]))
+        .features(CargoOpt::NoDefaultFeatures)
+        .other_options(vec!["--frozen".into()])
+        .cargo_command();
+    let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+    assert_eq!(
+        args,
+        [
+            "metadata",
+            "--format-version",
+            "1",
+            "--features",
+            "feat1,feat2",
+            "--no-default-features",
+            "--manifest-path",
+            "tests/all/Cargo.toml",
+            "--frozen",
+        ]
+    );
+}
+
+#[test]
+fn format_version() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .format_version(1)
+        .exec()
+        .unwrap();
+    assert_eq!(meta.format_version(), 1);
+
+    let cmd = MetadataCommand::new().format_version(2).cargo_command();
+    let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+    assert_eq!(args, ["metadata", "--format-version", "2"]);
+}
+
+#[test]
+fn dependencies_of_kind() {
+    let metadata = MetadataCommand::new().no_deps().exec().unwrap();
+    let this = &metadata.packages[0];
+    assert!(this.normal_dependencies().any(|dep| dep.name == "serde"));
+    assert!(!this.dev_dependencies().any(|dep| dep.name == "serde"));
+
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .exec()
+        .unwrap();
+    let all = metadata.root_package().unwrap();
+    let names = |deps: Vec<&cargo_metadata::Dependency>| -> Vec<String> {
+        deps.into_iter().map(|dep| dep.name.clone()).collect()
+    };
+    assert_eq!(names(all.dev_dependencies().collect()), ["devdep"]);
+    assert_eq!(names(all.build_dependencies().collect()), ["bdep"]);
+    assert_eq!(
+        names(all.dependencies_of_kind(DependencyKind::Normal).collect()),
+        names(all.normal_dependencies().collect())
+    );
+    assert!(all
+        .normal_dependencies()
+        .all(|dep| dep.kind == DependencyKind::Normal));
+
+    assert!(DependencyKind::Normal < DependencyKind::Build);
+    assert!(DependencyKind::Build < DependencyKind::Development);
+    assert_eq!(
+        names(all.sorted_dependencies()),
+        ["bitflags", "featdep", "namedep", "oldname", "path-dep", "windep", "bdep", "devdep"]
+    );
+}
+
+#[test]
+fn target_selection() {
+    let metadata = MetadataCommand::new().no_deps().exec().unwrap();
+    let this = &metadata.packages[0];
+    assert_eq!(this.lib_target().unwrap().name, "cargo_metadata");
+    assert_eq!(this.bin_targets().count(), 0);
+    let roots: Vec<_> = this.source_roots().collect();
+    assert_eq!(roots.len(), this.targets.len());
+    assert!(roots.iter().any(|root| root.ends_with("src/lib.rs")));
+    assert!(roots.iter().any(|root| root.ends_with("tests/selftest.rs")));
+
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .exec()
+        .unwrap();
+    let all = metadata.root_package().unwrap();
+    assert_eq!(all.lib_target().unwrap().name, "all");
+    let mut bins: Vec<_> = all.bin_targets().map(|bin| bin.name.as_str()).collect();
+    bins.sort();
+    assert_eq!(bins, ["all", "otherbin", "reqfeat"]);
+}
+
+#[test]
+fn verbosity() {
+    let mut cmd = MetadataCommand::new();
+    cmd.manifest_path("tests/all/Cargo.toml").verbosity(2);
+    let args: Vec<_> = cmd
+        .cargo_command()
+        .get_args()
+        .map(|arg| arg.to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(args.iter().filter(|arg| *arg == "-v").count(), 2);
+
+    let meta = cmd.verbosity(1).exec().unwrap();
+    assert_eq!(meta.root_package().unwrap().name, "all");
+}
+
 #[test]
 fn error1() {
     match MetadataCommand::new().manifest_path("foo").exec() {
-        Err(Error::CargoMetadata { stderr }) => assert_eq!(
-            stderr.trim(),
-            "error: the manifest-path must be a path to a Cargo.toml file"
-        ),
+        Err(Error::CargoMetadata { stderr }) => {
+            // Newer versions of cargo report the missing file instead.
+            let stderr = stderr.trim();
+            assert!(
+                stderr == "error: the manifest-path must be a path to a Cargo.toml file"
+                    || stderr == "error: manifest path `foo` does not exist",
+                "unexpected stderr: {}",
+                stderr
+            );
+        }
         _ => unreachable!(),
     }
 }
@@ -117,6 +257,24 @@ fn cargo_path() {
     }
 }
 
+#[test]
+fn toolchain() {
+    let has_stable = std::process::Command::new("rustup")
+        .args(["run", "stable", "cargo", "-V"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !has_stable {
+        eprintln!("Skipping toolchain test, rustup or the stable toolchain is not available.");
+        return;
+    }
+    let metadata = MetadataCommand::new()
+        .toolchain("stable")
+        .no_deps()
+        .exec()
+        .unwrap();
+    assert_eq!(metadata.packages[0].name, "cargo_metadata");
+}
+
 #[test]
 fn metadata_deps() {
     std::env::set_var("CARGO_PROFILE", "3");
@@ -135,6 +293,13 @@ fn metadata_deps() {
     let workspace_packages = metadata.workspace_packages();
     assert_eq!(workspace_packages.len(), 1);
     assert_eq!(&workspace_packages[0].id, this_id);
+    assert!(metadata.is_workspace_member(this_id));
+    let serde = metadata
+        .packages
+        .iter()
+        .find(|pkg| pkg.name == "serde")
+        .unwrap();
+    assert!(!metadata.is_workspace_member(&serde.id));
 
     let lib = this
         .targets
@@ -176,3 +341,350 @@ fn workspace_default_packages() {
         assert_eq!(default_packages, workspace_packages);
     }
 }
+
+#[test]
+fn build_command() {
+    // Use a separate target directory so that this doesn't wait for the lock held by
+    // the `cargo test` invocation running it.
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("build_command");
+    let messages = BuildCommand::new()
+        .lib()
+        .env("CARGO_TARGET_DIR", target_dir)
+        .exec()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let artifact = messages
+        .iter()
+        .find_map(|message| match message {
+            Message::CompilerArtifact(artifact) if artifact.target.name == "cargo_metadata" => {
+                Some(artifact)
+            }
+            _ => None,
+        })
+        .expect("no artifact for the library");
+    assert_eq!(artifact.target.kind[0], "lib".into());
+    assert!(matches!(
+        messages.last(),
+        Some(Message::BuildFinished(finished)) if finished.success
+    ));
+
+    let command = BuildCommand::new()
+        .bins()
+        .bin("foo")
+        .examples()
+        .tests()
+        .cargo_command();
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        [
+            "build",
+            "--message-format=json",
+            "--bins",
+            "--bin",
+            "foo",
+            "--examples",
+            "--tests"
+        ]
+    );
+
+    let command = BuildCommand::new()
+        .all_targets()
+        .target("wasm32-unknown-unknown")
+        .cargo_command();
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        [
+            "build",
+            "--message-format=json",
+            "--all-targets",
+            "--target",
+            "wasm32-unknown-unknown"
+        ]
+    );
+}
+
+#[test]
+fn build_command_target() {
+    const TARGET: &str = "wasm32-unknown-unknown";
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .unwrap()
+        .stdout;
+    let sysroot = PathBuf::from(String::from_utf8(sysroot).unwrap().trim());
+    if !sysroot.join("lib/rustlib").join(TARGET).exists() {
+        eprintln!("skipping, the {} target is not installed", TARGET);
+        return;
+    }
+
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("build_command_target");
+    let messages = BuildCommand::new()
+        .lib()
+        .target(TARGET)
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .exec()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let artifact = messages
+        .iter()
+        .find_map(|message| match message {
+            Message::CompilerArtifact(artifact) if artifact.target.name == "cargo_metadata" => {
+                Some(artifact)
+            }
+            _ => None,
+        })
+        .expect("no artifact for the library");
+    for filename in &artifact.filenames {
+        assert!(
+            filename.starts_with(target_dir.join(TARGET)),
+            "{}",
+            filename
+        );
+    }
+}
+
+#[test]
+fn build_command_error() {
+    match BuildCommand::new()
+        .manifest_path("does/not/exist/Cargo.toml")
+        .exec()
+    {
+        Err(Error::CargoBuild { stderr }) => assert!(stderr.contains("does/not/exist")),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("building a missing manifest succeeded"),
+    }
+}
+
+#[test]
+fn packages_by_source() {
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let groups = metadata.packages_by_source();
+    let names = |source: Option<&Source>| {
+        let mut names: Vec<_> = groups[&source.cloned()]
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let local = names(None);
+    assert!(local.contains(&"all"));
+    assert!(local.contains(&"path-dep"));
+    assert!(!local.contains(&"bitflags"));
+
+    let crates_io = groups
+        .keys()
+        .flatten()
+        .find(|source| source.is_crates_io())
+        .expect("no crates.io packages");
+    assert!(names(Some(crates_io)).contains(&"bitflags"));
+    assert_eq!(
+        groups.values().map(Vec::len).sum::<usize>(),
+        metadata.packages.len()
+    );
+}
+
+#[test]
+fn package() {
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .package("bitflags")
+        .exec()
+        .unwrap();
+    assert_eq!(metadata.packages.len(), 1);
+    assert_eq!(metadata.packages[0].name, "bitflags");
+
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .package("all")
+        .exec()
+        .unwrap();
+    assert_eq!(metadata.packages.len(), 1);
+    assert_eq!(metadata.packages[0].id, metadata.workspace_members[0]);
+
+    match MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .package("bitflags")
+        .exec()
+    {
+        Err(Error::PackageNotFound { name }) => assert_eq!(name, "bitflags"),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn no_deps_graph_helpers() {
+    let metadata = MetadataCommand::new().no_deps().exec().unwrap();
+    assert!(!metadata.has_resolve());
+    assert_eq!(metadata.root_package().unwrap().name, "cargo_metadata");
+    assert!(metadata.dependency_graph_roots().is_empty());
+    assert!(metadata.dependency_edges().is_empty());
+    assert!(metadata.all_enabled_features().is_empty());
+    assert!(metadata.feature_diff(&metadata).is_empty());
+    assert_eq!(metadata.validate(), Ok(()));
+    let affected = metadata.packages_affected_by(current_dir().unwrap().join("src/lib.rs"));
+    assert_eq!(affected.len(), 1);
+    assert_eq!(affected[0].name, "cargo_metadata");
+
+    let with_deps = MetadataCommand::new().exec().unwrap();
+    assert!(with_deps.has_resolve());
+    assert!(!with_deps.dependency_graph_roots().is_empty());
+}
+
+#[test]
+fn proc_macro() {
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/proc_macro/Cargo.toml")
+        .no_deps()
+        .exec()
+        .unwrap();
+    let package = &metadata.packages[0];
+    assert!(package.is_proc_macro());
+    let lib = package.lib_target().unwrap();
+    assert!(lib.is_proc_macro());
+    assert_eq!(lib.crate_types, vec!["proc-macro".into()]);
+    assert!(lib.runs_doctests());
+
+    let this = MetadataCommand::new().no_deps().exec().unwrap();
+    assert!(!this.packages[0].is_proc_macro());
+}
+
+#[test]
+fn config() {
+    let mut command = MetadataCommand::new();
+    command
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .config("net.offline=true");
+    assert_eq!(
+        command.cargo_command().get_args().collect::<Vec<_>>(),
+        [
+            "metadata",
+            "--format-version",
+            "1",
+            "--no-deps",
+            "--manifest-path",
+            "tests/all/Cargo.toml",
+            "--config",
+            "net.offline=true"
+        ]
+    );
+    let metadata = command.exec().unwrap();
+    assert_eq!(metadata.packages[0].name, "all");
+
+    match command.config("this is not toml").exec() {
+        Err(Error::CargoMetadata { stderr }) => assert!(stderr.contains("--config")),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn timeout() {
+    let metadata = MetadataCommand::new()
+        .no_deps()
+        .timeout(Duration::from_secs(60))
+        .exec()
+        .unwrap();
+    assert_eq!(metadata.packages[0].name, "cargo_metadata");
+}
+
+#[cfg(unix)]
+#[test]
+fn timeout_kills_cargo() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cargo = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("sleepy-cargo");
+    std::fs::write(&cargo, "#!/bin/sh\nexec sleep 30\n").unwrap();
+    std::fs::set_permissions(&cargo, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let start = Instant::now();
+    match MetadataCommand::new()
+        .cargo_path(&cargo)
+        .timeout(Duration::from_millis(200))
+        .exec()
+    {
+        Err(Error::Timeout { elapsed }) => assert!(elapsed >= Duration::from_millis(200)),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn exec_async() {
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec_async()
+        .await
+        .unwrap();
+    let blocking = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    assert_eq!(metadata, blocking);
+
+    match MetadataCommand::new()
+        .manifest_path("does/not/exist/Cargo.toml")
+        .exec_async()
+        .await
+    {
+        Err(Error::CargoMetadata { stderr }) => assert!(stderr.contains("does/not/exist")),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn exec_raw() {
+    let (metadata, json) = MetadataCommand::new().no_deps().exec_raw().unwrap();
+    assert_eq!(MetadataCommand::parse(&json).unwrap(), metadata);
+    assert_eq!(metadata.packages[0].name, "cargo_metadata");
+}
+
+#[test]
+fn dependencies_for_target() {
+    use cargo_metadata::cargo_platform::Cfg;
+
+    let metadata = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .no_deps()
+        .exec()
+        .unwrap();
+    let all = metadata.root_package().unwrap();
+    let windep = all
+        .dependencies
+        .iter()
+        .find(|dep| dep.name == "windep")
+        .unwrap();
+    let bitflags = all
+        .dependencies
+        .iter()
+        .find(|dep| dep.name == "bitflags")
+        .unwrap();
+
+    let linux = [
+        Cfg::Name("unix".into()),
+        Cfg::KeyPair("target_os".into(), "linux".into()),
+    ];
+    assert!(!windep.applies_to(&linux, "x86_64-unknown-linux-gnu"));
+    assert!(bitflags.applies_to(&linux, "x86_64-unknown-linux-gnu"));
+    let windows = [
+        Cfg::Name("windows".into()),
+        Cfg::KeyPair("target_os".into(), "windows".into()),
+    ];
+    assert!(windep.applies_to(&windows, "x86_64-pc-windows-msvc"));
+
+    let on_linux = all.dependencies_for_target("x86_64-unknown-linux-gnu", &linux);
+    assert_eq!(on_linux.len(), all.dependencies.len() - 1);
+    assert!(!on_linux.iter().any(|dep| dep.name == "windep"));
+    let on_windows = all.dependencies_for_target("x86_64-pc-windows-msvc", &windows);
+    assert_eq!(on_windows.len(), all.dependencies.len());
+    assert!(on_windows.iter().any(|dep| dep.name == "windep"));
+}
@@ -4,8 +4,12 @@ extern crate semver;
 extern crate serde_json;
 
 use camino::Utf8PathBuf;
+use cargo_metadata::diagnostic::{Applicability, Diagnostic, DiagnosticLevel, DiagnosticSpan};
 use cargo_metadata::{
-    ArtifactDebuginfo, CargoOpt, DependencyKind, Edition, Message, Metadata, MetadataCommand,
+    ArtifactDebuginfo, BuildFinished, CargoOpt, CrateType, DependencyKind, DependencySource,
+    Edition, Error, FeatureValue, FeatureValueError, GitReference, Message, Metadata,
+    MetadataCommand, OptLevel, PackageId, PackageLookup, RunSelectionError, Source, SourceKind,
+    Target, ValidationError,
 };
 
 /// Output from oldest version ever supported (1.24).
@@ -65,6 +69,48 @@ const JSON_OLD_MINIMAL: &str = r#"
 }
 "#;
 
+#[test]
+fn from_reader_and_slice() {
+    let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    assert_eq!(
+        Metadata::from_slice(JSON_OLD_MINIMAL.as_bytes()).unwrap(),
+        meta
+    );
+    assert_eq!(
+        Metadata::from_reader(JSON_OLD_MINIMAL.as_bytes()).unwrap(),
+        meta
+    );
+
+    let serialized = serde_json::to_vec(&meta).unwrap();
+    assert_eq!(Metadata::from_slice(&serialized).unwrap(), meta);
+    assert_eq!(
+        Metadata::from_reader(std::io::Cursor::new(serialized)).unwrap(),
+        meta
+    );
+
+    assert!(matches!(
+        Metadata::from_slice(b"{\"packages\": 1}"),
+        Err(Error::Json(_))
+    ));
+}
+
+#[test]
+fn parse_strict() {
+    MetadataCommand::parse_strict(JSON_OLD_MINIMAL).unwrap();
+
+    let mut json: serde_json::Value = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    json["not_a_field"] = true.into();
+    json["packages"][0]["targets"][0]["frobnicate"] = 1.into();
+    // The lenient parser ignores unknown fields.
+    MetadataCommand::parse(json.to_string()).unwrap();
+    match MetadataCommand::parse_strict(json.to_string()) {
+        Err(Error::UnknownFields { paths }) => {
+            assert_eq!(paths, ["not_a_field", "packages.0.targets.0.frobnicate"])
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
 #[test]
 fn old_minimal() {
     let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
@@ -122,6 +168,10 @@ fn old_minimal() {
     assert!(meta.resolve.is_none());
     assert_eq!(meta.workspace_root, "/foo");
     assert_eq!(meta.workspace_metadata, serde_json::Value::Null);
+    assert!(meta
+        .workspace_metadata_typed::<serde_json::Value>()
+        .unwrap()
+        .is_none());
     assert_eq!(meta.target_directory, "/foo/target");
 
     assert!(!meta.workspace_default_members.is_available());
@@ -134,6 +184,395 @@ fn old_minimal() {
         .contains_key("workspace_default_members"));
 }
 
+#[test]
+fn package_id_parts() {
+    let id = |repr: &str| PackageId { repr: repr.into() };
+    let parts = |id: PackageId| {
+        (
+            id.name().map(str::to_owned),
+            id.version(),
+            id.source_url().map(str::to_owned),
+        )
+    };
+    let expected = |name: &str, version: &str, source: &str| {
+        (
+            Some(name.to_owned()),
+            Some(semver::Version::parse(version).unwrap()),
+            Some(source.to_owned()),
+        )
+    };
+
+    assert_eq!(
+        parts(id("foo 0.1.0 (path+file:///foo)")),
+        expected("foo", "0.1.0", "path+file:///foo")
+    );
+    assert_eq!(
+        parts(id(
+            "serde 1.0.136 (registry+https://github.com/rust-lang/crates.io-index)"
+        )),
+        expected(
+            "serde",
+            "1.0.136",
+            "registry+https://github.com/rust-lang/crates.io-index"
+        )
+    );
+    assert_eq!(
+        parts(id("path+file:///my/foo#0.1.0")),
+        expected("foo", "0.1.0", "path+file:///my/foo")
+    );
+    assert_eq!(
+        parts(id(
+            "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.136"
+        )),
+        expected(
+            "serde",
+            "1.0.136",
+            "registry+https://github.com/rust-lang/crates.io-index"
+        )
+    );
+    assert_eq!(parts(id("not an id")), (None, None, None));
+}
+
+#[test]
+fn package_id_parse_spec() {
+    let spec = |repr: &str| {
+        let spec = PackageId { repr: repr.into() }.parse_spec().unwrap();
+        (spec.kind, spec.url, spec.name, spec.version.to_string())
+    };
+    let expected = |kind, url: &str, name: &str, version: &str| {
+        (kind, url.to_owned(), name.to_owned(), version.to_owned())
+    };
+
+    let path = expected(SourceKind::Path, "file:///my/foo", "foo", "0.1.0");
+    assert_eq!(spec("foo 0.1.0 (path+file:///my/foo)"), path);
+    assert_eq!(spec("path+file:///my/foo#0.1.0"), path);
+    assert_eq!(spec("path+file:///my/foo#foo@0.1.0"), path);
+
+    let registry = expected(
+        SourceKind::Registry,
+        "https://github.com/rust-lang/crates.io-index",
+        "serde",
+        "1.0.136",
+    );
+    assert_eq!(
+        spec("serde 1.0.136 (registry+https://github.com/rust-lang/crates.io-index)"),
+        registry
+    );
+    assert_eq!(
+        spec("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.136"),
+        registry
+    );
+    assert_eq!(
+        spec("sparse+https://index.crates.io/#serde@1.0.136").0,
+        SourceKind::SparseRegistry
+    );
+
+    // The legacy format includes the resolved commit, the new one doesn't.
+    assert_eq!(
+        spec("bar 0.2.0 (git+https://github.com/foo/bar?branch=main#0123abcd)"),
+        expected(
+            SourceKind::Git,
+            "https://github.com/foo/bar?branch=main#0123abcd",
+            "bar",
+            "0.2.0"
+        )
+    );
+    assert_eq!(
+        spec("git+https://github.com/foo/bar?branch=main#bar@0.2.0"),
+        expected(
+            SourceKind::Git,
+            "https://github.com/foo/bar?branch=main",
+            "bar",
+            "0.2.0"
+        )
+    );
+
+    assert_eq!(
+        spec("directory+file:///vendor/foo#foo@0.1.0").0,
+        SourceKind::Other("directory".into())
+    );
+    let invalid = PackageId {
+        repr: "not an id".into(),
+    };
+    assert_eq!(invalid.parse_spec(), None);
+}
+
+#[test]
+fn package_id_short() {
+    let id = |repr: &str| PackageId { repr: repr.into() };
+    let serde = Some(("serde".to_owned(), semver::Version::new(1, 0, 136)));
+
+    let legacy = id("serde 1.0.136 (registry+https://github.com/rust-lang/crates.io-index)");
+    assert_eq!(legacy.name_version(), serde);
+    assert_eq!(legacy.short(), "serde v1.0.136");
+
+    let spec = id("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.136");
+    assert_eq!(spec.name_version(), serde);
+    assert_eq!(spec.short(), "serde v1.0.136");
+
+    let path = id("path+file:///my/foo#0.1.0");
+    assert_eq!(path.short(), "foo v0.1.0");
+
+    let invalid = id("foo 1.x (path+file:///foo)");
+    assert_eq!(invalid.name_version(), None);
+    assert_eq!(invalid.short(), "foo 1.x (path+file:///foo)");
+}
+
+#[test]
+fn validate() {
+    let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    assert_eq!(meta.validate(), Ok(()));
+
+    let json = JSON_OLD_MINIMAL.replace(
+        r#""resolve": null"#,
+        r#""resolve": {
+            "nodes": [
+              {
+                "id": "foo 0.1.0 (path+file:///foo)",
+                "dependencies": ["somedep 1.0.0 (path+file:///somedep)"]
+              },
+              {
+                "id": "bar 0.1.0 (path+file:///bar)",
+                "dependencies": []
+              }
+            ],
+            "root": "baz 0.1.0 (path+file:///baz)"
+        }"#,
+    );
+    let meta: Metadata = serde_json::from_str(&json).unwrap();
+    let id = |repr: &str| PackageId { repr: repr.into() };
+    assert_eq!(
+        meta.validate(),
+        Err(vec![
+            ValidationError::UnknownRoot {
+                id: id("baz 0.1.0 (path+file:///baz)")
+            },
+            ValidationError::UnknownDependency {
+                from: id("foo 0.1.0 (path+file:///foo)"),
+                to: id("somedep 1.0.0 (path+file:///somedep)")
+            },
+            ValidationError::UnknownNode {
+                id: id("bar 0.1.0 (path+file:///bar)")
+            },
+        ])
+    );
+}
+
+#[test]
+fn default_binary() {
+    let mut meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let pkg = &mut meta.packages[0];
+
+    // A single binary is picked without `default-run`.
+    assert_eq!(pkg.default_binary().unwrap().name, "foo");
+
+    let mut other = pkg.targets[0].clone();
+    other.name = "bar".into();
+    pkg.targets.push(other);
+    assert_eq!(
+        pkg.default_binary(),
+        Err(RunSelectionError::Ambiguous {
+            candidates: vec!["foo".into(), "bar".into()]
+        })
+    );
+
+    pkg.default_run = Some("bar".into());
+    assert_eq!(pkg.default_binary().unwrap().name, "bar");
+
+    pkg.default_run = Some("baz".into());
+    assert_eq!(
+        pkg.default_binary(),
+        Err(RunSelectionError::DefaultRunNotFound { name: "baz".into() })
+    );
+
+    pkg.default_run = None;
+    pkg.targets.clear();
+    assert_eq!(pkg.default_binary(), Err(RunSelectionError::NoBinaries));
+}
+
+#[test]
+fn target_crate_types() {
+    let json = r#"{"kind":["cdylib","rlib"],"crate_types":["cdylib","rlib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":false,"test":true}"#;
+    let target: Target = serde_json::from_str(json).unwrap();
+    assert_eq!(target.crate_types, [CrateType::CDyLib, CrateType::RLib]);
+    assert!(target.produces_cdylib());
+    assert!(target.produces_rlib());
+    assert!(!target.produces_staticlib());
+    assert!(!target.produces_bin());
+    assert!(target.produces(CrateType::CDyLib));
+
+    let json = json.replace(
+        r#""crate_types":["cdylib","rlib"]"#,
+        r#""crate_types":["sdylib"]"#,
+    );
+    let target: Target = serde_json::from_str(&json).unwrap();
+    assert_eq!(target.crate_types, [CrateType::Unknown("sdylib".into())]);
+    assert!(target.produces(CrateType::Unknown("sdylib".into())));
+}
+
+#[test]
+fn license_ids() {
+    let mut meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let pkg = &mut meta.packages[0];
+    assert!(pkg.license_ids().is_empty());
+    assert!(!pkg.has_license("MIT"));
+
+    let mut ids = |license: &str| {
+        pkg.license = Some(license.into());
+        pkg.license_ids()
+    };
+    assert_eq!(ids("MIT"), ["MIT"]);
+    assert_eq!(ids("MIT OR Apache-2.0"), ["MIT", "Apache-2.0"]);
+    assert_eq!(
+        ids("(MIT OR Apache-2.0) AND BSD-3-Clause"),
+        ["MIT", "Apache-2.0", "BSD-3-Clause"]
+    );
+    assert_eq!(
+        ids("Apache-2.0 WITH LLVM-exception OR MIT OR Apache-2.0"),
+        ["Apache-2.0", "MIT"]
+    );
+    assert_eq!(ids("MIT/Apache-2.0"), ["MIT", "Apache-2.0"]);
+
+    assert!(pkg.has_license("Apache-2.0"));
+    assert!(!pkg.has_license("BSD-3-Clause"));
+}
+
+#[test]
+fn cmp_by_name_version() {
+    let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let package = |name: &str, version: &str| {
+        let mut pkg = meta.packages[0].clone();
+        pkg.name = name.into();
+        pkg.version = version.parse().unwrap();
+        pkg
+    };
+    let mut packages = [
+        package("foo", "0.10.0"),
+        package("bar", "1.0.0"),
+        package("foo", "0.2.0"),
+        package("foo", "0.10.0-alpha"),
+    ];
+    packages.sort_by(|a, b| a.cmp_by_name_version(b));
+    let sorted: Vec<_> = packages
+        .iter()
+        .map(|p| format!("{} {}", p.name, p.version))
+        .collect();
+    assert_eq!(
+        sorted,
+        ["bar 1.0.0", "foo 0.2.0", "foo 0.10.0-alpha", "foo 0.10.0"]
+    );
+}
+
+#[test]
+fn implicit_optional_dep_features() {
+    let mut meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let pkg = &mut meta.packages[0];
+    assert!(pkg.implicit_optional_dep_features().is_empty());
+
+    pkg.dependencies[0].optional = true;
+    let implicit = ["somedep".to_string()].into_iter().collect();
+    assert_eq!(pkg.implicit_optional_dep_features(), implicit);
+
+    // Cargo lists the implicit feature itself since 1.60.
+    pkg.features
+        .insert("somedep".into(), vec!["dep:somedep".into()]);
+    assert_eq!(pkg.implicit_optional_dep_features(), implicit);
+
+    // Referring to the dependency with `dep:` elsewhere suppresses the implicit feature.
+    pkg.features.clear();
+    pkg.features
+        .insert("extra".into(), vec!["dep:somedep".into()]);
+    assert!(pkg.implicit_optional_dep_features().is_empty());
+
+    pkg.features.clear();
+    pkg.edition = serde_json::from_str(r#""2024""#).unwrap();
+    assert!(pkg.implicit_optional_dep_features().is_empty());
+}
+
+#[test]
+fn feature_value_from_str() {
+    assert_eq!("std".parse(), Ok(FeatureValue::Feature("std".into())));
+    assert_eq!(
+        "dep:serde".parse(),
+        Ok(FeatureValue::Dep {
+            dep_name: "serde".into()
+        })
+    );
+    assert_eq!(
+        "serde/derive".parse(),
+        Ok(FeatureValue::DepFeature {
+            dep_name: "serde".into(),
+            dep_feature: "derive".into(),
+            weak: false,
+        })
+    );
+    assert_eq!(
+        "serde?/derive".parse(),
+        Ok(FeatureValue::DepFeature {
+            dep_name: "serde".into(),
+            dep_feature: "derive".into(),
+            weak: true,
+        })
+    );
+    for invalid in [
+        "",
+        "dep:",
+        "dep:serde/derive",
+        "serde/",
+        "/derive",
+        "serde/derive/more",
+        "serde?",
+    ] {
+        let err = invalid.parse::<FeatureValue>().unwrap_err();
+        assert!(matches!(
+            &err,
+            FeatureValueError::Invalid { value, .. } if value == invalid
+        ));
+    }
+}
+
+#[test]
+fn feature_value_round_trip() {
+    for value in [
+        "std",
+        "serde_json",
+        "dep:serde",
+        "dep:new-name",
+        "serde/derive",
+        "serde?/derive",
+        "tokio?/rt-multi-thread",
+    ] {
+        assert_eq!(value.parse::<FeatureValue>().unwrap().to_string(), value);
+    }
+}
+
+#[test]
+fn enabled_features_closure() {
+    let mut meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let pkg = &mut meta.packages[0];
+    pkg.features
+        .insert("a".into(), vec!["b".into(), "dep:x".into(), "y/z".into()]);
+    pkg.features
+        .insert("b".into(), vec!["a".into(), "c".into()]);
+    pkg.features.insert("c".into(), vec![]);
+    pkg.features.insert("d".into(), vec!["a".into()]);
+
+    // Cycles between features terminate.
+    assert_eq!(
+        pkg.enabled_features_closure("a"),
+        ["a", "b", "c"].iter().map(|s| s.to_string()).collect()
+    );
+    assert_eq!(
+        pkg.enabled_features_closure("d"),
+        ["a", "b", "c", "d"].iter().map(|s| s.to_string()).collect()
+    );
+    // Unknown features only enable themselves.
+    assert_eq!(
+        pkg.enabled_features_closure("e"),
+        ["e"].iter().map(|s| s.to_string()).collect()
+    );
+}
+
 macro_rules! sorted {
     ($e:expr) => {{
         let mut v = $e.clone();
@@ -198,6 +637,7 @@ fn all_the_fields() {
         .exec()
         .unwrap();
     assert_eq!(meta.workspace_root.file_name().unwrap(), "all");
+    assert_eq!(meta.resolver_version(), Some(1));
     assert_eq!(
         serde_json::from_value::<WorkspaceMetadata>(meta.workspace_metadata.clone()).unwrap(),
         WorkspaceMetadata {
@@ -206,6 +646,14 @@ fn all_the_fields() {
             }
         }
     );
+    assert_eq!(
+        meta.workspace_metadata_typed::<WorkspaceMetadata>()
+            .unwrap()
+            .unwrap()
+            .testobject
+            .myvalue,
+        "abc"
+    );
     assert_eq!(meta.workspace_members.len(), 1);
     assert!(meta.workspace_members[0].to_string().contains("all"));
     if ver >= semver::Version::parse("1.71.0").unwrap() {
@@ -224,6 +672,7 @@ fn all_the_fields() {
     assert_eq!(all.publish, Some(vec![]));
     assert_eq!(all.links, Some("foo".to_string()));
     assert_eq!(all.default_run, Some("otherbin".to_string()));
+    assert_eq!(all.default_binary().unwrap().name, "otherbin");
     if ver >= semver::Version::parse("1.58.0").unwrap() {
         assert_eq!(
             all.rust_version,
@@ -319,6 +768,7 @@ fn all_the_fields() {
     assert_eq!(lib.required_features.len(), 0);
     assert_eq!(lib.edition, Edition::E2018);
     assert!(lib.doctest);
+    assert!(lib.runs_doctests());
     assert!(lib.test);
     assert!(lib.doc);
 
@@ -326,6 +776,7 @@ fn all_the_fields() {
     assert_eq!(main.crate_types, vec!["bin".into()]);
     assert_eq!(main.kind, vec!["bin".into()]);
     assert!(!main.doctest);
+    assert!(!main.runs_doctests());
     assert!(main.test);
     assert!(main.doc);
 
@@ -345,6 +796,12 @@ fn all_the_fields() {
 
     let b1 = get_file_name!("b1.rs");
     assert_eq!(b1.kind, vec!["bench".into()]);
+    assert_eq!(b1.edition, Edition::E2021);
+
+    assert_eq!(
+        all.target_editions().into_iter().collect::<Vec<_>>(),
+        vec![Edition::E2015, Edition::E2018, Edition::E2021]
+    );
 
     let build = get_file_name!("build.rs");
     assert_eq!(build.kind, vec!["custom-build".into()]);
@@ -359,6 +816,17 @@ fn all_the_fields() {
     assert_eq!(all.features["feat1"].len(), 0);
     assert_eq!(all.features["feat2"].len(), 0);
     assert_eq!(sorted!(all.features["default"]), vec!["bitflags", "feat1"]);
+    assert_eq!(
+        all.enabled_features_closure("default"),
+        ["bitflags", "default", "feat1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    );
+    assert_eq!(
+        all.implicit_optional_dep_features(),
+        ["bitflags".to_string()].into_iter().collect()
+    );
 
     assert!(all.manifest_path.ends_with("all/Cargo.toml"));
     assert_eq!(all.categories, vec!["command-line-utilities"]);
@@ -442,6 +910,25 @@ fn all_the_fields() {
         .unwrap();
     assert!(namedep.pkg.to_string().contains("namedep"));
     assert_eq!(sorted!(all.features), vec!["bitflags", "default", "feat1"]);
+    let root = resolve.root.as_ref().unwrap();
+    assert_eq!(resolve.node(root), Some(all));
+    assert_eq!(
+        sorted!(resolve.enabled_features(root).to_vec()),
+        vec!["bitflags", "default", "feat1"]
+    );
+    let unknown = PackageId {
+        repr: "unknown".into(),
+    };
+    assert!(resolve.node(&unknown).is_none());
+    assert!(resolve.enabled_features(&unknown).is_empty());
+
+    assert_eq!(resolve.dependents(&bitflags.id), vec![root]);
+    assert_eq!(
+        resolve.transitive_dependents(&bitflags.id),
+        std::collections::BTreeSet::from([root.clone()])
+    );
+    assert!(resolve.dependents(root).is_empty());
+    assert!(resolve.transitive_dependents(root).is_empty());
 
     let bdep = all.deps.iter().find(|d| d.name == "bdep").unwrap();
     assert_eq!(bdep.dep_kinds.len(), 1);
@@ -454,6 +941,9 @@ fn all_the_fields() {
     let kind = &devdep.dep_kinds[0];
     assert_eq!(kind.kind, DependencyKind::Development);
     assert!(kind.target.is_none());
+    assert!(!devdep.is_normal());
+    assert!(!bdep.is_normal());
+    assert!(path_dep.is_normal());
 
     let windep = all.deps.iter().find(|d| d.name == "windep").unwrap();
     assert_eq!(windep.dep_kinds.len(), 1);
@@ -477,6 +967,405 @@ fn all_the_fields() {
     }
 }
 
+#[test]
+fn dependency_edges() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let all = meta.root_package().unwrap();
+    let edges = meta.dependency_edges();
+    let edge_to = |name: &str| {
+        edges
+            .iter()
+            .find(|e| e.from == all.id && meta[&e.to].name == name)
+            .unwrap()
+    };
+
+    assert_eq!(edges.iter().filter(|e| e.from == all.id).count(), 8);
+
+    let featdep = edge_to("featdep");
+    assert_eq!(featdep.kind, DependencyKind::Normal);
+    assert_eq!(featdep.features, vec!["i128"]);
+
+    // Renamed dependencies are matched through their new name.
+    let oldname = edge_to("oldname");
+    assert_eq!(oldname.kind, DependencyKind::Normal);
+    assert_eq!(oldname.features, vec!["feat"]);
+
+    assert_eq!(edge_to("devdep").kind, DependencyKind::Development);
+    assert_eq!(edge_to("bdep").kind, DependencyKind::Build);
+}
+
+/// Metadata of `tests/renamed_deps`, which depends on `twice` 0.1 as `twice` and on
+/// `twice` 0.2 as `twice2`, with a different feature each.
+fn renamed_deps_metadata() -> Metadata {
+    MetadataCommand::new()
+        .manifest_path("tests/renamed_deps/Cargo.toml")
+        .exec()
+        .unwrap()
+}
+
+#[test]
+fn dependency_edges_same_package_renamed() {
+    let meta = renamed_deps_metadata();
+    let root = meta.root_package().unwrap();
+    let edges = meta.dependency_edges();
+    let features_to = |version: &str| {
+        let edge = edges
+            .iter()
+            .find(|e| {
+                let to = &meta[&e.to];
+                e.from == root.id && to.name == "twice" && to.version.to_string() == version
+            })
+            .unwrap();
+        edge.features.clone()
+    };
+    assert_eq!(features_to("0.1.0"), ["plain"]);
+    assert_eq!(features_to("0.2.0"), ["renamed"]);
+}
+
+#[test]
+fn dependency_edges_after_prune() {
+    let mut meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    assert!(!meta.dependency_edges().is_empty());
+    meta.prune_to_workspace();
+    // `all` is the only package left and all of its dependencies are gone.
+    assert!(meta.dependency_edges().is_empty());
+}
+
+#[test]
+fn prune_to_workspace() {
+    let mut meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let members = meta.workspace_members.clone();
+    let nodes = meta.resolve.as_ref().unwrap().nodes.len();
+    meta.prune_to_workspace();
+
+    assert_eq!(meta.workspace_members, members);
+    assert_eq!(meta.packages.len(), 1);
+    assert_eq!(meta.packages[0].name, "all");
+    assert!(!meta.packages.iter().any(|p| p.name == "bitflags"));
+    // Every node is reachable from the root.
+    assert_eq!(meta.resolve.as_ref().unwrap().nodes.len(), nodes);
+    assert!(meta.validate().is_err());
+}
+
+#[test]
+fn features_for_dep() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let resolve = meta.resolve.as_ref().unwrap();
+    let root = resolve.node(resolve.root.as_ref().unwrap()).unwrap();
+    let features_for = |name: &str| {
+        let dep = root
+            .deps
+            .iter()
+            .find(|dep| meta[&dep.pkg].name == name)
+            .unwrap();
+        root.features_for_dep(dep, &meta)
+    };
+
+    // `featdep = { features = ["i128"], default-features = false }`
+    assert_eq!(features_for("featdep"), ["i128"]);
+    assert_eq!(features_for("bitflags"), ["default"]);
+    // `path-dep` has no `default` feature
+    assert!(features_for("path-dep").is_empty());
+    // `newname = { package = "oldname", features = ["feat"] }`
+    assert_eq!(features_for("oldname"), ["feat"]);
+}
+
+#[test]
+fn features_for_dep_same_package_renamed() {
+    let meta = renamed_deps_metadata();
+    let resolve = meta.resolve.as_ref().unwrap();
+    let root = resolve.node(resolve.root.as_ref().unwrap()).unwrap();
+    let features_for = |name: &str| {
+        let dep = root.deps.iter().find(|dep| dep.name == name).unwrap();
+        root.features_for_dep(dep, &meta)
+    };
+    assert_eq!(features_for("twice"), ["plain"]);
+    // `helper` enables `plain` on `twice` 0.2 as well, but not through this edge.
+    assert_eq!(features_for("twice2"), ["renamed"]);
+}
+
+#[test]
+fn crates_io_url() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let bitflags = meta.packages.iter().find(|p| p.name == "bitflags").unwrap();
+    assert_eq!(
+        bitflags.crates_io_url(),
+        Some(format!(
+            "https://crates.io/crates/bitflags/{}",
+            bitflags.version
+        ))
+    );
+    let path_dep = meta.packages.iter().find(|p| p.name == "path-dep").unwrap();
+    assert_eq!(path_dep.crates_io_url(), None);
+
+    let mut git: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    let git = &mut git.packages[0];
+    git.source = Some(Source {
+        repr: "git+https://github.com/oli-obk/cargo_metadata#5bb4749".into(),
+    });
+    assert_eq!(git.crates_io_url(), None);
+}
+
+#[test]
+fn source_registry() {
+    let source = |repr: &str| Source { repr: repr.into() };
+
+    let git_index = source("registry+https://github.com/rust-lang/crates.io-index");
+    assert!(git_index.is_crates_io());
+    assert_eq!(
+        git_index.registry_url(),
+        Some("https://github.com/rust-lang/crates.io-index")
+    );
+
+    let sparse_index = source("sparse+https://index.crates.io/");
+    assert!(sparse_index.is_crates_io());
+    assert_eq!(
+        sparse_index.registry_url(),
+        Some("https://index.crates.io/")
+    );
+
+    let alt = source("sparse+https://my-intranet:8080/index/");
+    assert!(!alt.is_crates_io());
+    assert_eq!(alt.registry_url(), Some("https://my-intranet:8080/index/"));
+
+    let git = source("git+https://github.com/oli-obk/cargo_metadata#5bb4749");
+    assert!(!git.is_crates_io());
+    assert_eq!(git.registry_url(), None);
+}
+
+#[test]
+fn dependency_graph_roots() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    assert_eq!(meta.validate(), Ok(()));
+    let roots = meta.dependency_graph_roots();
+    let all = meta.root_package().unwrap();
+    assert_eq!(roots, vec![&all.id]);
+    let featdep = meta.packages.iter().find(|p| p.name == "featdep").unwrap();
+    assert!(!roots.contains(&&featdep.id));
+
+    let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    assert!(meta.dependency_graph_roots().is_empty());
+}
+
+#[test]
+fn package_lookup() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let lookup = PackageLookup::new(&meta);
+    let resolve = meta.resolve.as_ref().unwrap();
+    for node in &resolve.nodes {
+        assert_eq!(lookup.get(&node.id), Some(&meta[&node.id]));
+    }
+    // The lookup hands out the very packages of the metadata, not copies.
+    let root = resolve.root.as_ref().unwrap();
+    let bitflags = &resolve
+        .node(root)
+        .unwrap()
+        .deps
+        .iter()
+        .find(|dep| dep.name == "bitflags")
+        .unwrap()
+        .pkg;
+    assert!(std::ptr::eq(lookup.get(bitflags).unwrap(), &meta[bitflags]));
+
+    let named: Vec<_> = meta.packages_named("featdep").collect();
+    assert_eq!(named.len(), 1);
+    assert_eq!(named[0].name, "featdep");
+    assert_eq!(meta.packages_named("nonexistent").count(), 0);
+    assert_eq!(
+        meta.package_named_version("featdep", &named[0].version),
+        Some(named[0])
+    );
+    assert_eq!(
+        meta.package_named_version("featdep", &semver::Version::new(99, 0, 0)),
+        None
+    );
+
+    let featdep = lookup.get_by_name("featdep");
+    assert_eq!(featdep.len(), 1);
+    assert_eq!(featdep[0].name, "featdep");
+    assert!(lookup.get_by_name("nonexistent").is_empty());
+    assert_eq!(
+        lookup.get(&PackageId {
+            repr: "nonexistent".into()
+        }),
+        None
+    );
+}
+
+#[test]
+fn dependency_kind_of_source() {
+    let dep = |source: &str, registry: &str, path: &str| -> cargo_metadata::Dependency {
+        serde_json::from_value(json!({
+            "name": "dep",
+            "source": serde_json::from_str::<serde_json::Value>(source).unwrap(),
+            "req": "*",
+            "kind": null,
+            "optional": false,
+            "uses_default_features": true,
+            "features": [],
+            "target": null,
+            "registry": serde_json::from_str::<serde_json::Value>(registry).unwrap(),
+            "path": serde_json::from_str::<serde_json::Value>(path).unwrap(),
+        }))
+        .unwrap()
+    };
+
+    assert_eq!(
+        dep(
+            r#""registry+https://github.com/rust-lang/crates.io-index""#,
+            "null",
+            "null"
+        )
+        .kind_of_source(),
+        Some(DependencySource::CratesIo)
+    );
+    assert_eq!(
+        dep(
+            r#""registry+https://example.com""#,
+            r#""https://example.com""#,
+            "null"
+        )
+        .kind_of_source(),
+        Some(DependencySource::Registry("https://example.com".into()))
+    );
+    assert_eq!(
+        dep(r#""sparse+https://index.crates.io/""#, "null", "null").kind_of_source(),
+        Some(DependencySource::CratesIo)
+    );
+    assert_eq!(
+        dep(r#""sparse+https://example.com/index/""#, "null", "null").kind_of_source(),
+        Some(DependencySource::Registry(
+            "https://example.com/index/".into()
+        ))
+    );
+    assert_eq!(
+        dep(
+            r#""git+https://github.com/oli-obk/cargo_metadata?branch=main""#,
+            "null",
+            "null"
+        )
+        .kind_of_source(),
+        Some(DependencySource::Git {
+            url: "https://github.com/oli-obk/cargo_metadata".into(),
+            reference: Some(GitReference::Branch("main".into())),
+        })
+    );
+    assert_eq!(
+        dep(
+            r#""git+https://github.com/oli-obk/cargo_metadata?rev=9cb07eb#9cb07eb""#,
+            "null",
+            "null"
+        )
+        .kind_of_source(),
+        Some(DependencySource::Git {
+            url: "https://github.com/oli-obk/cargo_metadata".into(),
+            reference: Some(GitReference::Rev("9cb07eb".into())),
+        })
+    );
+    assert_eq!(
+        dep("null", "null", r#""/foo/path-dep""#).kind_of_source(),
+        Some(DependencySource::Path("/foo/path-dep".into()))
+    );
+    // Path dependencies before cargo 1.51 have neither a source nor a path.
+    assert_eq!(dep("null", "null", "null").kind_of_source(), None);
+}
+
+#[cfg(feature = "borrowed")]
+#[test]
+fn borrowed_metadata() {
+    use cargo_metadata::borrowed::MetadataRef;
+
+    let output = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .cargo_command()
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json = std::str::from_utf8(&output.stdout).unwrap();
+    let owned = MetadataCommand::parse(json).unwrap();
+    let borrowed: MetadataRef = serde_json::from_str(json).unwrap();
+
+    assert_eq!(borrowed.workspace_root, owned.workspace_root.as_str());
+    assert_eq!(borrowed.target_directory, owned.target_directory.as_str());
+    assert_eq!(
+        borrowed.workspace_members.len(),
+        owned.workspace_members.len()
+    );
+    for (b, o) in borrowed
+        .workspace_members
+        .iter()
+        .zip(&owned.workspace_members)
+    {
+        assert_eq!(b, &o.repr[..]);
+    }
+
+    assert_eq!(borrowed.packages.len(), owned.packages.len());
+    for (b, o) in borrowed.packages.iter().zip(&owned.packages) {
+        assert_eq!(b.name, o.name.as_str());
+        assert!(b.name.is_borrowed());
+        assert_eq!(b.version, &o.version.to_string()[..]);
+        assert_eq!(b.id, &o.id.repr[..]);
+        assert_eq!(
+            b.source.as_deref(),
+            o.source.as_ref().map(|s| s.repr.as_str())
+        );
+        assert_eq!(b.manifest_path, o.manifest_path.as_str());
+        assert_eq!(b.dependencies.len(), o.dependencies.len());
+        for (bd, od) in b.dependencies.iter().zip(&o.dependencies) {
+            assert_eq!(bd.name, od.name.as_str());
+            assert_eq!(bd.kind, od.kind);
+            assert_eq!(bd.optional, od.optional);
+            assert_eq!(bd.rename.as_deref(), od.rename.as_deref());
+        }
+        assert_eq!(b.targets.len(), o.targets.len());
+        for (bt, ot) in b.targets.iter().zip(&o.targets) {
+            assert_eq!(bt.name, ot.name.as_str());
+            assert_eq!(bt.src_path, ot.src_path.as_str());
+        }
+        let features: std::collections::BTreeMap<&str, Vec<&str>> = b
+            .features
+            .iter()
+            .map(|(k, v)| (&**k, v.iter().map(|v| &**v).collect()))
+            .collect();
+        let owned_features: std::collections::BTreeMap<&str, Vec<&str>> = o
+            .features
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(features, owned_features);
+    }
+
+    let (b, o) = (borrowed.resolve.unwrap(), owned.resolve.unwrap());
+    assert_eq!(b.root.as_deref(), o.root.as_ref().map(|r| r.repr.as_str()));
+    assert_eq!(b.nodes.len(), o.nodes.len());
+    for (bn, on) in b.nodes.iter().zip(&o.nodes) {
+        assert_eq!(bn.id, &on.id.repr[..]);
+        assert_eq!(bn.dependencies.len(), on.dependencies.len());
+        assert_eq!(bn.features.len(), on.features.len());
+    }
+}
+
 #[test]
 fn alt_registry() {
     // This is difficult to test (would need to set up a custom index).
@@ -556,6 +1445,45 @@ fn current_dir() {
     assert!(namedep.name.starts_with("namedep"));
 }
 
+#[test]
+fn packages_affected_by() {
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/basic_workspace/Cargo.toml")
+        .exec()
+        .unwrap();
+    let names = |packages: Vec<&cargo_metadata::Package>| {
+        sorted!(packages.iter().map(|p| p.name.clone()).collect::<Vec<_>>())
+    };
+    let root = &meta.workspace_root;
+
+    assert_eq!(
+        meta.package_for_path(root.join("ex_lib/src/lib.rs"))
+            .unwrap()
+            .name,
+        "ex_lib"
+    );
+    assert_eq!(
+        meta.package_for_path(root.join("src/main.rs"))
+            .unwrap()
+            .name,
+        "ex_bin"
+    );
+    assert!(meta.package_for_path("/elsewhere/src/lib.rs").is_none());
+
+    // Editing the library affects the binary depending on it.
+    assert_eq!(
+        names(meta.packages_affected_by(root.join("ex_lib/src/lib.rs"))),
+        vec!["ex_bin", "ex_lib"]
+    );
+    assert_eq!(
+        names(meta.packages_affected_by(root.join("src/main.rs"))),
+        vec!["ex_bin"]
+    );
+    assert!(meta
+        .packages_affected_by("/elsewhere/src/lib.rs")
+        .is_empty());
+}
+
 #[test]
 fn parse_stream_is_robust() {
     // Proc macros can print stuff to stdout, which naturally breaks JSON messages.
@@ -577,6 +1505,474 @@ Evil proc macro was here!
     assert_eq!(text, "Evil proc macro was here!");
 }
 
+#[test]
+fn parse_stream_skips_invalid_utf8() {
+    let input = b"{\"reason\":\"build-finished\",\"success\":false}\nbad \xff line\n{\"reason\":\"build-finished\",\"success\":true}\n";
+
+    let mut messages = Message::parse_stream(&input[..]);
+    assert!(matches!(
+        messages.next(),
+        Some(Ok(Message::BuildFinished(BuildFinished {
+            success: false,
+            ..
+        })))
+    ));
+    let err = messages.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(
+        messages.next(),
+        Some(Ok(Message::BuildFinished(BuildFinished {
+            success: true,
+            ..
+        })))
+    ));
+    assert!(messages.next().is_none());
+
+    let results: Vec<_> = Message::parse_stream_results(&input[..]).collect();
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[1], Err(Error::Io(_))));
+    assert!(matches!(
+        results[2],
+        Ok(Message::BuildFinished(BuildFinished { success: true, .. }))
+    ));
+}
+
+#[test]
+fn build_finished() {
+    let json_output = r#"{"reason":"build-finished","success":true}
+{"reason":"build-finished","success":false}
+"#;
+    let finished: Vec<bool> = Message::parse_stream(json_output.as_bytes())
+        .map(|message| match message.unwrap() {
+            Message::BuildFinished(finished) => finished.success,
+            message => panic!("unexpected {:?}", message),
+        })
+        .collect();
+    assert_eq!(finished, vec![true, false]);
+}
+
+#[test]
+fn diagnostic_levels() {
+    for (spelling, level, is_error) in [
+        ("error: internal compiler error", DiagnosticLevel::Ice, true),
+        ("error", DiagnosticLevel::Error, true),
+        ("warning", DiagnosticLevel::Warning, false),
+        ("failure-note", DiagnosticLevel::FailureNote, false),
+        ("note", DiagnosticLevel::Note, false),
+        ("help", DiagnosticLevel::Help, false),
+        ("cry-for-help", DiagnosticLevel::Unknown, false),
+    ] {
+        let json = format!(
+            r#"{{"message":"","code":null,"level":"{}","spans":[],"children":[],"rendered":null}}"#,
+            spelling
+        );
+        let diagnostic: Diagnostic = serde_json::from_str(&json).unwrap();
+        assert_eq!(diagnostic.level, level);
+        assert_eq!(diagnostic.is_error(), is_error);
+        if level != DiagnosticLevel::Unknown {
+            assert_eq!(level.to_string(), spelling);
+        }
+    }
+}
+
+/// An unused variable warning recorded from rustc, with a second, not machine-applicable
+/// suggestion added.
+const JSON_UNUSED_VARIABLE: &str = r#"{"rendered":null,"$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(unused_variables)]` on by default","rendered":null,"spans":[]},{"children":[{"children":[],"code":null,"level":"help","message":"or remove it","rendered":null,"spans":[{"byte_end":26,"byte_start":16,"column_end":15,"column_start":5,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"","suggestion_applicability":"MaybeIncorrect","text":[]}]}],"code":null,"level":"help","message":"if this is intentional, prefix it with an underscore","rendered":null,"spans":[{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"_x","suggestion_applicability":"MachineApplicable","text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}]}],"level":"warning","message":"unused variable: `x`","spans":[{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}],"code":{"code":"unused_variables","explanation":null}}"#;
+
+#[test]
+fn machine_applicable_suggestions() {
+    let diagnostic: Diagnostic = serde_json::from_str(JSON_UNUSED_VARIABLE).unwrap();
+    let suggestions = diagnostic.machine_applicable_suggestions();
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].file_name, "src/main.rs");
+    assert_eq!(suggestions[0].byte_start, 20);
+    assert_eq!(suggestions[0].byte_end, 21);
+    assert_eq!(suggestions[0].replacement, "_x");
+}
+
+#[test]
+fn diagnostic_all_spans() {
+    // One span on the warning, one on the "prefix it with an underscore" help and one
+    // on its "remove it" child.
+    let diagnostic: Diagnostic = serde_json::from_str(JSON_UNUSED_VARIABLE).unwrap();
+    let replacements: Vec<_> = diagnostic
+        .all_spans()
+        .map(|span| span.suggested_replacement.as_deref())
+        .collect();
+    assert_eq!(replacements, [None, Some("_x"), Some("")]);
+
+    let primary = diagnostic.primary_span().unwrap();
+    assert_eq!((primary.byte_start, primary.byte_end), (20, 21));
+    assert!(diagnostic.children[0].primary_span().is_none());
+}
+
+#[test]
+fn diagnostic_affected_files() {
+    let span = |file_name: &str| {
+        format!(
+            r#"{{"byte_end":1,"byte_start":0,"column_end":2,"column_start":1,"expansion":null,"file_name":"{}","is_primary":true,"label":null,"line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}}"#,
+            file_name
+        )
+    };
+    let json = format!(
+        r#"{{"message":"mismatched types","code":null,"level":"error","spans":[{},{}],"children":[{{"message":"defined here","code":null,"level":"note","spans":[{},{}],"children":[],"rendered":null}}],"rendered":null}}"#,
+        span("src/main.rs"),
+        span("<anon>"),
+        span("src/lib.rs"),
+        span("src/main.rs"),
+    );
+    let diagnostic: Diagnostic = serde_json::from_str(&json).unwrap();
+    assert_eq!(diagnostic.all_spans().count(), 4);
+    assert_eq!(
+        diagnostic.affected_files().into_iter().collect::<Vec<_>>(),
+        ["src/lib.rs", "src/main.rs"]
+    );
+}
+
+#[test]
+fn diagnostic_span_ranges() {
+    let source = "fn main() {\n    let x = 1;\n}\n";
+    let json = r#"{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"_x","suggestion_applicability":"MachineApplicable","text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}"#;
+    let span: DiagnosticSpan = serde_json::from_str(json).unwrap();
+    assert_eq!(span.byte_range(), 20..21);
+    assert_eq!(&source[span.byte_range()], "x");
+    assert_eq!(span.line_col_range(), ((2, 9), (2, 10)));
+}
+
+#[test]
+fn diagnostic_dedup() {
+    let json = r#"{"message":"unused variable: `x`","code":null,"level":"warning","spans":[{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"_x","suggestion_applicability":"MachineApplicable","text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}],"children":[],"rendered":null}"#;
+    let diagnostics: std::collections::HashSet<Diagnostic> = (0..2)
+        .map(|_| serde_json::from_str(json).unwrap())
+        .collect();
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn applicability() {
+    for (applicability, machine_applicable) in [
+        (Applicability::MachineApplicable, true),
+        (Applicability::HasPlaceholders, false),
+        (Applicability::MaybeIncorrect, false),
+        (Applicability::Unspecified, false),
+    ] {
+        assert_eq!(applicability.is_machine_applicable(), machine_applicable);
+        assert_eq!(applicability.can_autofix(), machine_applicable);
+    }
+    assert_eq!(Applicability::default(), Applicability::Unspecified);
+}
+
+#[test]
+fn diagnostic_render() {
+    // Recorded from rustc, with a child note added.
+    let json = r#"{"rendered":"error[E0308]: mismatched types\n --> src/main.rs:2:18\n","$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"integer and string literals never coerce","rendered":null,"spans":[]}],"level":"error","message":"mismatched types","spans":[{"byte_end":32,"byte_start":29,"column_end":21,"column_start":18,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":"expected `u32`, found `&str`","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":21,"highlight_start":18,"text":"    let x: u32 = \"a\";"}]},{"byte_end":26,"byte_start":23,"column_end":15,"column_start":12,"expansion":null,"file_name":"src/main.rs","is_primary":false,"label":"expected due to this","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":15,"highlight_start":12,"text":"    let x: u32 = \"a\";"}]}],"code":{"code":"E0308","explanation":null}}"#;
+    let diagnostic: Diagnostic = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        diagnostic.render(),
+        r#"error[E0308]: mismatched types
+ --> src/main.rs:2:18
+  |
+2 |     let x: u32 = "a";
+  |                  ^^^ expected `u32`, found `&str`
+ --> src/main.rs:2:12
+  |
+2 |     let x: u32 = "a";
+  |            --- expected due to this
+  note: integer and string literals never coerce
+"#
+    );
+}
+
+#[test]
+fn diagnostic_rendered_plain() {
+    let json = r#"{"$message_type":"diagnostic","children":[],"code":null,"level":"warning","message":"unused variable: `x`","spans":[],"rendered":"\u001b[0m\u001b[1m\u001b[33mwarning\u001b[0m\u001b[0m\u001b[1m: unused variable: `x`\u001b[0m\n\u001b[2Kdone \u001b[38;5;12m|\u001b[0m"}"#;
+    let mut diagnostic: Diagnostic = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        diagnostic.rendered_plain().unwrap(),
+        "warning: unused variable: `x`\n\u{1b}[2Kdone |"
+    );
+
+    diagnostic.rendered = None;
+    assert_eq!(diagnostic.rendered_plain(), None);
+}
+
+#[test]
+fn parse_stream_is_fused() {
+    struct Broken;
+    impl std::io::Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken pipe"))
+        }
+    }
+
+    let input = std::io::Read::chain(
+        &br#"{"reason":"build-finished","success":true}
+"#[..],
+        Broken,
+    );
+    let mut messages = Message::parse_stream(std::io::BufReader::new(input));
+    assert!(matches!(
+        messages.next(),
+        Some(Ok(Message::BuildFinished(_)))
+    ));
+    let err: cargo_metadata::Error = messages.next().unwrap().unwrap_err().into();
+    assert!(matches!(err, cargo_metadata::Error::Io(_)));
+    assert!(messages.next().is_none());
+    assert!(messages.next().is_none());
+
+    let mut messages = Message::parse_stream(&b"not json"[..]);
+    assert!(matches!(messages.next(), Some(Ok(Message::TextLine(_)))));
+    assert!(messages.next().is_none());
+    assert!(messages.next().is_none());
+}
+
+#[test]
+fn artifact_filenames_with_spaces() {
+    let json_output = r#"{"reason":"compiler-artifact","package_id":"path+file:///my%20project#0.1.0","manifest_path":"/my project/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/my project/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/my project/target/debug/libfoo.rlib","/my project/target/debug/deps/libfoo-4d3b9fa0e2ba6ea8.rmeta"],"executable":null,"fresh":false}"#;
+    let artifact = match serde_json::from_str(json_output).unwrap() {
+        Message::CompilerArtifact(artifact) => artifact,
+        message => panic!("unexpected {:?}", message),
+    };
+    assert_eq!(
+        artifact.filenames,
+        vec![
+            Utf8PathBuf::from("/my project/target/debug/libfoo.rlib"),
+            Utf8PathBuf::from("/my project/target/debug/deps/libfoo-4d3b9fa0e2ba6ea8.rmeta"),
+        ]
+    );
+    assert_eq!(artifact.filenames[0].file_name(), Some("libfoo.rlib"));
+}
+
+#[test]
+fn serde_round_trip() {
+    fn round_trip<T>(value: &T)
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_value(value).unwrap();
+        assert_eq!(&serde_json::from_value::<T>(json).unwrap(), value);
+    }
+
+    let meta: Metadata = serde_json::from_str(JSON_OLD_MINIMAL).unwrap();
+    round_trip(&meta);
+
+    let meta = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    round_trip(&meta);
+    for pkg in &meta.packages {
+        round_trip(pkg);
+    }
+
+    // `rust-version` is normalized to a full version when parsing.
+    let mut pkg = meta.packages[0].clone();
+    pkg.rust_version = Some(semver::Version::new(1, 70, 0));
+    round_trip(&pkg);
+
+    let json_output = r#"{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"foo","src_path":"/foo/src/main.rs","edition":"2021","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":["default"],"filenames":["/foo/target/debug/foo"],"executable":"/foo/target/debug/foo","fresh":true}
+{"reason":"build-finished","success":true}"#;
+    for message in Message::parse_stream(json_output.as_bytes()) {
+        let message = message.unwrap();
+        if let Message::CompilerArtifact(artifact) = &message {
+            round_trip(artifact);
+        }
+        round_trip(&message);
+    }
+
+    for debuginfo in [
+        ArtifactDebuginfo::None,
+        ArtifactDebuginfo::LineDirectivesOnly,
+        ArtifactDebuginfo::LineTablesOnly,
+        ArtifactDebuginfo::Limited,
+        ArtifactDebuginfo::Full,
+        ArtifactDebuginfo::UnknownInt(42),
+        ArtifactDebuginfo::UnknownString("more".into()),
+    ] {
+        round_trip(&debuginfo);
+    }
+
+    // Text lines aren't cargo messages and can't be serialized.
+    assert!(serde_json::to_value(Message::TextLine("hello".into())).is_err());
+}
+
+#[test]
+fn test_executables() {
+    // Recorded from `cargo test --no-run --message-format=json`.
+    let json_output = r#"{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/foo/target/debug/deps/libfoo-4d3b9fa0e2ba6ea8.rmeta"],"executable":null,"fresh":false}
+{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":true},"features":[],"filenames":["/foo/target/debug/deps/foo-8b4ea8f4b1d29a3c"],"executable":"/foo/target/debug/deps/foo-8b4ea8f4b1d29a3c","fresh":false}
+{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["test"],"crate_types":["bin"],"name":"integration","src_path":"/foo/tests/integration.rs","edition":"2021","doc":false,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":true},"features":[],"filenames":["/foo/target/debug/deps/integration-0f3c1d6fe4a8b2c1"],"executable":"/foo/target/debug/deps/integration-0f3c1d6fe4a8b2c1","fresh":false}
+{"reason":"build-finished","success":true}
+"#;
+    let executables: Vec<_> = cargo_metadata::test_executables(json_output.as_bytes())
+        .map(|executable| executable.unwrap())
+        .collect();
+    assert_eq!(executables.len(), 2);
+    assert!(executables
+        .iter()
+        .all(|(id, _)| id.repr == "path+file:///foo#0.1.0"));
+    assert_eq!(
+        executables[0].1,
+        "/foo/target/debug/deps/foo-8b4ea8f4b1d29a3c"
+    );
+    assert_eq!(
+        executables[1].1,
+        "/foo/target/debug/deps/integration-0f3c1d6fe4a8b2c1"
+    );
+}
+
+#[test]
+fn collect_executables() {
+    // Recorded from `cargo build --message-format=json` of a package with a library and two
+    // binaries.
+    let json_output = r#"{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2024","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/foo/target/debug/libfoo.rlib","/foo/target/debug/deps/libfoo-0256163055ff45e3.rmeta"],"executable":null,"fresh":false}
+{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"foo","src_path":"/foo/src/main.rs","edition":"2024","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/foo/target/debug/foo"],"executable":"/foo/target/debug/foo","fresh":false}
+{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"other","src_path":"/foo/src/bin/other.rs","edition":"2024","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/foo/target/debug/other"],"executable":"/foo/target/debug/other","fresh":false}
+{"reason":"build-finished","success":true}
+"#;
+    let executables = cargo_metadata::collect_executables(json_output.as_bytes()).unwrap();
+    assert_eq!(
+        executables,
+        vec![
+            Utf8PathBuf::from("/foo/target/debug/foo"),
+            Utf8PathBuf::from("/foo/target/debug/other"),
+        ]
+    );
+}
+
+#[test]
+fn build_script_env() {
+    let json = r#"{"reason":"build-script-executed","package_id":"path+file:///foo#0.1.0","linked_libs":[],"linked_paths":[],"cfgs":[],"env":[["FOO","1"],["BAR","2"],["FOO","3"]],"out_dir":"/foo/target/debug/build/foo-1234/out"}"#;
+    let script = match serde_json::from_str::<Message>(json).unwrap() {
+        Message::BuildScriptExecuted(script) => script,
+        message => panic!("unexpected message: {:?}", message),
+    };
+    assert_eq!(script.env_var("FOO"), Some("3"));
+    assert_eq!(script.env_var("BAR"), Some("2"));
+    assert_eq!(script.env_var("BAZ"), None);
+    assert_eq!(
+        script.env_map(),
+        std::collections::BTreeMap::from([("BAR", "2"), ("FOO", "3")])
+    );
+}
+
+#[test]
+fn build_script_linking() {
+    let json = r#"{"reason":"build-script-executed","package_id":"path+file:///foo#0.1.0","linked_libs":["static=foo","static:+whole-archive=bar","baz"],"linked_paths":["native=/usr/lib","/opt/lib","/weird=path"],"cfgs":[],"env":[],"out_dir":"/foo/target/debug/build/foo-1234/out"}"#;
+    let script = match serde_json::from_str::<Message>(json).unwrap() {
+        Message::BuildScriptExecuted(script) => script,
+        message => panic!("unexpected message: {:?}", message),
+    };
+    assert_eq!(
+        script.parsed_linked_paths(),
+        [
+            (Some("native".into()), Utf8PathBuf::from("/usr/lib")),
+            (None, Utf8PathBuf::from("/opt/lib")),
+            (None, Utf8PathBuf::from("/weird=path")),
+        ]
+    );
+    assert_eq!(
+        script.parsed_linked_libs(),
+        [
+            (Some("static".into()), "foo".into()),
+            (Some("static:+whole-archive".into()), "bar".into()),
+            (None, "baz".into()),
+        ]
+    );
+}
+
+#[test]
+fn build_script_cfgs() {
+    use cargo_metadata::cargo_platform::Cfg;
+
+    let json = r#"{"reason":"build-script-executed","package_id":"path+file:///foo#0.1.0","linked_libs":[],"linked_paths":[],"cfgs":["unix","feature=\"serde\"","target_os = \"linux\""],"env":[],"out_dir":"/foo/target/debug/build/foo-1234/out"}"#;
+    let mut script = match serde_json::from_str::<Message>(json).unwrap() {
+        Message::BuildScriptExecuted(script) => script,
+        message => panic!("unexpected message: {:?}", message),
+    };
+    assert_eq!(
+        script.parsed_cfgs().unwrap(),
+        [
+            Cfg::Name("unix".into()),
+            Cfg::KeyPair("feature".into(), "serde".into()),
+            Cfg::KeyPair("target_os".into(), "linux".into()),
+        ]
+    );
+
+    script.cfgs.push("feature=serde".into());
+    assert!(script.parsed_cfgs().is_err());
+}
+
+#[test]
+fn collect_diagnostics() {
+    // Recorded from `cargo build --message-format=json` of a library with an unused_parens
+    // warning and a type error.
+    let json_output = r#"{"reason":"compiler-message","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2024","doc":true,"doctest":true,"test":true},"message":{"rendered":"warning: unnecessary parentheses around block return value\n --> src/lib.rs:3:5\n  |\n3 |     (y)\n  |     ^ ^\n  |\n  = note: `#[warn(unused_parens)]` (part of `#[warn(unused)]`) on by default\nhelp: remove these parentheses\n  |\n3 -     (y)\n3 +     y \n  |\n\n","$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(unused_parens)]` (part of `#[warn(unused)]`) on by default","rendered":null,"spans":[]},{"children":[],"code":null,"level":"help","message":"remove these parentheses","rendered":null,"spans":[{"byte_end":47,"byte_start":46,"column_end":6,"column_start":5,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":"","suggestion_applicability":"MachineApplicable","text":[{"highlight_end":6,"highlight_start":5,"text":"    (y)"}]},{"byte_end":49,"byte_start":48,"column_end":8,"column_start":7,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":" ","suggestion_applicability":"MachineApplicable","text":[{"highlight_end":8,"highlight_start":7,"text":"    (y)"}]}]}],"level":"warning","message":"unnecessary parentheses around block return value","spans":[{"byte_end":47,"byte_start":46,"column_end":6,"column_start":5,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":6,"highlight_start":5,"text":"    (y)"}]},{"byte_end":49,"byte_start":48,"column_end":8,"column_start":7,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":8,"highlight_start":7,"text":"    (y)"}]}],"code":{"code":"unused_parens","explanation":null}}}
+{"reason":"compiler-message","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2024","doc":true,"doctest":true,"test":true},"message":{"rendered":"error[E0308]: mismatched types\n --> src/lib.rs:2:18\n  |\n2 |     let y: u32 = \"a\";\n  |            ---   ^^^ expected `u32`, found `&str`\n  |            |\n  |            expected due to this\n\n","$message_type":"diagnostic","children":[],"level":"error","message":"mismatched types","spans":[{"byte_end":40,"byte_start":37,"column_end":21,"column_start":18,"expansion":null,"file_name":"src/lib.rs","is_primary":true,"label":"expected `u32`, found `&str`","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":21,"highlight_start":18,"text":"    let y: u32 = \"a\";"}]},{"byte_end":34,"byte_start":31,"column_end":15,"column_start":12,"expansion":null,"file_name":"src/lib.rs","is_primary":false,"label":"expected due to this","line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":15,"highlight_start":12,"text":"    let y: u32 = \"a\";"}]}],"code":{"code":"E0308","explanation":"Expected type did not match the received type.\n\nErroneous code examples:\n\n```compile_fail,E0308\nfn plus_one(x: i32) -> i32 {\n    x + 1\n}\n\nplus_one(\"Not a number\");\n//       ^^^^^^^^^^^^^^ expected `i32`, found `&str`\n\nif \"Not a bool\" {\n// ^^^^^^^^^^^^ expected `bool`, found `&str`\n}\n\nlet x: f32 = \"Not a float\";\n//     ---   ^^^^^^^^^^^^^ expected `f32`, found `&str`\n//     |\n//     expected due to this\n```\n\nThis error occurs when an expression was used in a place where the compiler\nexpected an expression of a different type. It can occur in several cases, the\nmost common being when calling a function and passing an argument which has a\ndifferent type than the matching type in the function declaration.\n"}}}
+{"reason":"compiler-message","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2024","doc":true,"doctest":true,"test":true},"message":{"rendered":"For more information about this error, try `rustc --explain E0308`.\n","$message_type":"diagnostic","children":[],"level":"failure-note","message":"For more information about this error, try `rustc --explain E0308`.","spans":[],"code":null}}
+{"reason":"build-finished","success":false}
+"#;
+    let levels = |min_level| {
+        cargo_metadata::collect_diagnostics(json_output.as_bytes(), min_level)
+            .unwrap()
+            .iter()
+            .map(|msg| msg.level())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        levels(DiagnosticLevel::Warning),
+        [DiagnosticLevel::Warning, DiagnosticLevel::Error]
+    );
+    assert_eq!(levels(DiagnosticLevel::Error), [DiagnosticLevel::Error]);
+    assert_eq!(levels(DiagnosticLevel::Ice), []);
+    assert_eq!(levels(DiagnosticLevel::Help).len(), 3);
+}
+
+#[test]
+fn group_by_package() {
+    let json_output = r#"{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/foo/target/debug/libfoo.rlib"],"executable":null,"fresh":false}
+{"reason":"build-script-executed","package_id":"path+file:///bar#0.1.0","linked_libs":[],"linked_paths":[],"cfgs":[],"env":[],"out_dir":"/bar/target/debug/build/bar-1234/out"}
+{"reason":"compiler-artifact","package_id":"path+file:///bar#0.1.0","manifest_path":"/bar/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"bar","src_path":"/bar/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/bar/target/debug/libbar.rlib"],"executable":null,"fresh":false}
+Compiling foo
+{"reason":"build-finished","success":true}
+"#;
+    let groups = cargo_metadata::group_by_package(json_output.as_bytes()).unwrap();
+    assert_eq!(groups.len(), 3);
+    let foo = PackageId {
+        repr: "path+file:///foo#0.1.0".to_string(),
+    };
+    let bar = PackageId {
+        repr: "path+file:///bar#0.1.0".to_string(),
+    };
+    assert_eq!(groups[&Some(foo.clone())].len(), 1);
+    let bar_messages = &groups[&Some(bar.clone())];
+    assert!(matches!(bar_messages[0], Message::BuildScriptExecuted(_)));
+    assert!(matches!(bar_messages[1], Message::CompilerArtifact(_)));
+    assert!(bar_messages.iter().all(|m| m.package_id() == Some(&bar)));
+    let unowned = &groups[&None];
+    assert_eq!(unowned.len(), 2);
+    assert!(matches!(unowned[0], Message::TextLine(_)));
+    assert!(matches!(unowned[1], Message::BuildFinished(_)));
+}
+
+#[test]
+fn some_features_enable_optional_dependency() {
+    let has_bitflags = |features: Vec<String>| {
+        MetadataCommand::new()
+            .manifest_path("tests/all/Cargo.toml")
+            .features(CargoOpt::NoDefaultFeatures)
+            .features(CargoOpt::SomeFeatures(features))
+            .exec()
+            .unwrap()
+            .packages_named("bitflags")
+            .count()
+            == 1
+    };
+    assert!(!has_bitflags(vec![]));
+    assert!(has_bitflags(vec!["bitflags".into()]));
+    assert!(has_bitflags(vec!["feat2".into(), "bitflags".into()]));
+}
+
 #[test]
 fn advanced_feature_configuration() {
     fn build_features<F: FnOnce(&mut MetadataCommand) -> &mut MetadataCommand>(
@@ -643,6 +2039,65 @@ fn advanced_feature_configuration() {
     assert_eq!(sorted!(all_flag_variants), sorted!(all_features));
 }
 
+#[test]
+fn feature_diff() {
+    let before = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .exec()
+        .unwrap();
+    let after = MetadataCommand::new()
+        .manifest_path("tests/all/Cargo.toml")
+        .features(CargoOpt::SomeFeatures(vec!["feat2".into()]))
+        .exec()
+        .unwrap();
+    assert!(before.feature_diff(&before).is_empty());
+
+    let all = before.root_package().unwrap();
+    let diff = before.feature_diff(&after);
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff[&all.id].added, vec!["feat2"]);
+    assert!(diff[&all.id].removed.is_empty());
+
+    let diff = after.feature_diff(&before);
+    assert!(diff[&all.id].added.is_empty());
+    assert_eq!(diff[&all.id].removed, vec!["feat2"]);
+}
+
+#[test]
+fn feature_fingerprint() {
+    let exec = |features: Vec<String>| {
+        MetadataCommand::new()
+            .manifest_path("tests/all/Cargo.toml")
+            .features(CargoOpt::SomeFeatures(features))
+            .exec()
+            .unwrap()
+    };
+    let default = exec(vec![]);
+    let feat2 = exec(vec!["feat2".into()]);
+
+    let all = default.root_package().unwrap();
+    let features = default.all_enabled_features();
+    assert_eq!(features.len(), default.packages.len());
+    assert_eq!(
+        sorted!(features[&all.id].to_vec()),
+        vec!["bitflags", "default", "feat1"]
+    );
+
+    assert_eq!(default.feature_fingerprint().len(), 16);
+    assert_eq!(
+        default.feature_fingerprint(),
+        exec(vec![]).feature_fingerprint()
+    );
+    assert_ne!(default.feature_fingerprint(), feat2.feature_fingerprint());
+
+    // Another checkout of the same workspace in a different location
+    let json = serde_json::to_string(&default).unwrap();
+    let moved = json.replace(default.workspace_root.as_str(), "/elsewhere/all");
+    assert_ne!(json, moved);
+    let moved: Metadata = serde_json::from_str(&moved).unwrap();
+    assert_eq!(default.feature_fingerprint(), moved.feature_fingerprint());
+}
+
 #[test]
 fn depkind_to_string() {
     assert_eq!(DependencyKind::Normal.to_string(), "normal");
@@ -716,6 +2171,25 @@ fn debuginfo_variants() {
     }
 }
 
+#[test]
+fn opt_level() {
+    let json = r#"{"reason":"compiler-artifact","package_id":"path+file:///foo#0.1.0","manifest_path":"/foo/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo","src_path":"/foo/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"s","debuginfo":0,"debug_assertions":false,"overflow_checks":false,"test":false},"features":[],"filenames":["/foo/target/release/libfoo.rlib"],"executable":null,"fresh":false}"#;
+    let artifact = match serde_json::from_str(json).unwrap() {
+        Message::CompilerArtifact(artifact) => artifact,
+        message => panic!("unexpected {:?}", message),
+    };
+    assert_eq!(artifact.profile.opt_level, OptLevel::S);
+
+    for level in ["0", "1", "2", "3", "s", "z", "fast"] {
+        let parsed: OptLevel = serde_json::from_value(json!(level)).unwrap();
+        assert_eq!(parsed, OptLevel::from(level));
+        assert_eq!(parsed.to_string(), level);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json!(level));
+    }
+    assert_eq!(OptLevel::from("3"), OptLevel::Three);
+    assert_eq!(OptLevel::from("fast"), OptLevel::Unknown("fast".into()));
+}
+
 #[test]
 #[should_panic = "WorkspaceDefaultMembers should only be dereferenced on Cargo versions >= 1.71"]
 fn missing_workspace_default_members() {
@@ -929,3 +2403,72 @@ fn test_unknown_target_kind_and_crate_type() {
     assert_eq!(target.kind[0], "future-kind".into());
     assert_eq!(target.crate_types[0], "future-type".into());
 }
+
+#[cfg(feature = "builder")]
+#[test]
+fn package_builder() {
+    use cargo_metadata::{PackageBuilder, TargetBuilder, TargetKind};
+
+    let target = TargetBuilder::default()
+        .name("fixture")
+        .kind(vec![TargetKind::Lib])
+        .crate_types(vec![CrateType::Lib])
+        .src_path("/fixture/src/lib.rs")
+        .build()
+        .unwrap();
+    let pkg = PackageBuilder::default()
+        .name("fixture")
+        .version(semver::Version::new(0, 1, 0))
+        .id(PackageId {
+            repr: "path+file:///fixture#0.1.0".into(),
+        })
+        .manifest_path("/fixture/Cargo.toml")
+        .targets(vec![target])
+        .build()
+        .unwrap();
+
+    assert_eq!(pkg.name, "fixture");
+    assert_eq!(pkg.source, None);
+    assert!(pkg.dependencies.is_empty());
+    assert_eq!(pkg.edition, Edition::E2015);
+    assert!(pkg.targets[0].produces_lib());
+    assert!(pkg.targets[0].test);
+
+    // The required fields have no defaults.
+    assert!(PackageBuilder::default().name("fixture").build().is_err());
+}
+
+#[cfg(feature = "builder")]
+#[test]
+fn resolve_builder() {
+    use cargo_metadata::{NodeBuilder, ResolveBuilder};
+
+    let id = |name: &str| PackageId {
+        repr: format!("{} 0.1.0", name),
+    };
+    let node = |name: &str, dependencies: &[&str]| {
+        NodeBuilder::default()
+            .id(id(name))
+            .dependencies(dependencies.iter().map(|d| id(d)).collect::<Vec<_>>())
+            .build()
+            .unwrap()
+    };
+    // app -> lib -> core, tool -> core
+    let resolve = ResolveBuilder::default()
+        .nodes(vec![
+            node("app", &["lib"]),
+            node("lib", &["core"]),
+            node("tool", &["core"]),
+            node("core", &[]),
+        ])
+        .root(Some(id("app")))
+        .build()
+        .unwrap();
+
+    assert_eq!(resolve.dependents(&id("core")), [&id("lib"), &id("tool")]);
+    assert_eq!(
+        resolve.transitive_dependents(&id("core")),
+        [id("app"), id("lib"), id("tool")].into_iter().collect()
+    );
+    assert!(resolve.transitive_dependents(&id("app")).is_empty());
+}